@@ -1,17 +1,34 @@
 use crate::const_val::*;
+use crate::error::{self, Cloud189Error};
 use crate::util;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+use chrono::Local;
 use regex::Regex;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{header, Client};
+use reqwest::{header, Client, RequestBuilder};
 use rsa::pkcs8::DecodePublicKey;
 use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 查询扫码状态的间隔
+const QR_CODE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// 密码登录最多输入验证码的次数
+const CAPTCHA_ATTEMPTS: usize = 3;
+
+/// 登录需要验证码时调用，参数为验证码图片，返回输入的验证码
+pub type CaptchaHandler = Arc<dyn Fn(&[u8]) -> Result<String> + Send + Sync>;
 
 pub struct CloudAuthClient {
     client: Client,
+    captcha_handler: CaptchaHandler,
+    urls: BaseUrls,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,24 +80,96 @@ struct LoginResponse {
     to_url: String,
 }
 
-#[derive(Serialize, Deserialize)]
+/// 登录接口共有的结果字段，`result`不为0时出错
+#[derive(Debug, Deserialize)]
+struct LoginStatus {
+    result: i64,
+    #[serde(default)]
+    msg: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TokenSession {
     #[serde(rename = "res_code")]
-    res_code: i64,
+    pub res_code: i64,
     #[serde(rename = "res_message")]
-    res_message: String,
-    access_token: String,
-    family_session_key: String,
-    family_session_secret: String,
-    refresh_token: String,
-    login_name: String,
-    session_key: String,
-    session_secret: String,
-    get_file_diff_span: i64,
-    get_user_info_span: i64,
-    is_save_name: String,
-    keep_alive: i64,
+    pub res_message: String,
+    pub access_token: String,
+    pub family_session_key: String,
+    pub family_session_secret: String,
+    pub refresh_token: String,
+    pub login_name: String,
+    pub session_key: String,
+    pub session_secret: String,
+    pub get_file_diff_span: i64,
+    pub get_user_info_span: i64,
+    pub is_save_name: String,
+    pub keep_alive: i64,
+}
+
+/// 扫码登录的二维码
+#[derive(Debug, Deserialize)]
+struct QrCode {
+    /// 二维码内容
+    uuid: String,
+    encryuuid: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QrCodeStateResponse {
+    status: i64,
+    #[serde(default)]
+    redirect_url: String,
+    #[serde(default)]
+    msg: String,
+}
+
+/// 扫码登录过程中需要告知用户的状态变化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QrCodeEvent {
+    /// 需要展示的二维码内容，二维码过期后会重新生成
+    Show(String),
+    /// 已扫码，等待手机确认
+    Scanned,
+}
+
+/// 二维码扫描状态
+#[derive(Debug, PartialEq, Eq)]
+enum QrCodeState {
+    /// 等待扫码
+    Waiting,
+    /// 已扫码，等待手机确认
+    Scanned,
+    Expired,
+    /// 登录成功，附带跳转地址
+    Success(String),
+    Failed(i64, String),
+}
+
+impl From<QrCodeStateResponse> for QrCodeState {
+    fn from(res: QrCodeStateResponse) -> Self {
+        match res.status {
+            0 => QrCodeState::Success(res.redirect_url),
+            -106 => QrCodeState::Waiting,
+            -11002 => QrCodeState::Scanned,
+            -11001 => QrCodeState::Expired,
+            status => QrCodeState::Failed(status, res.msg),
+        }
+    }
+}
+
+/// 刷新得到的访问令牌
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessToken {
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: String,
+    /// 有效期，单位秒
+    #[serde(default)]
+    pub expires_in: u64,
 }
 
 impl CloudAuthClient {
@@ -92,17 +181,48 @@ impl CloudAuthClient {
             HeaderValue::from_static("application/json;charset=UTF-8"),
         );
         let client = Client::builder().default_headers(headers).build()?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            captcha_handler: Arc::new(prompt_captcha),
+            urls: BaseUrls::default(),
+        })
+    }
+
+    /// 替换接口地址
+    pub fn with_base_urls(self, urls: BaseUrls) -> Self {
+        Self { urls, ..self }
+    }
+
+    /// 替换默认的终端输入验证码，例如接入打码服务
+    pub fn with_captcha_handler(
+        self,
+        handler: impl Fn(&[u8]) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            captcha_handler: Arc::new(handler),
+            ..self
+        }
+    }
+
+    /// 发送请求并解析json，`result`或状态字段表示出错时返回`Cloud189Error`
+    async fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if let Ok(res) = serde_json::from_slice::<LoginStatus>(&body)
+            && res.result != 0
+        {
+            return Err(Cloud189Error::from_login_result(res.result, &res.msg).into());
+        }
+        Ok(error::parse_response(status, &body)?)
     }
 
     async fn get_encrypt(&self) -> Result<Encrypt> {
-        Ok(self
-            .client
-            .post("https://open.e.189.cn/api/logbox/config/encryptConf.do")
-            .send()
-            .await?
-            .json::<Encrypt>()
-            .await?)
+        let request = self.client.post(format!(
+            "{}/api/logbox/config/encryptConf.do",
+            self.urls.auth
+        ));
+        self.send_json::<Encrypt>(request).await
     }
 
     async fn get_login_form(&self) -> Result<AppConf> {
@@ -112,7 +232,10 @@ impl CloudAuthClient {
             .as_millis();
         let res = self
             .client
-            .get("https://cloud.189.cn/api/portal/unifyLoginForPC.action")
+            .get(format!(
+                "{}/api/portal/unifyLoginForPC.action",
+                self.urls.web
+            ))
             .query(&json!({
                 "appId": APP_ID,
                 "clientType": CLIENT_TYPE,
@@ -158,46 +281,110 @@ impl CloudAuthClient {
         })
     }
 
-    async fn build_login_form(
+    fn build_login_form(
         &self,
-        encrypt: Encrypt,
-        app_conf: AppConf,
+        encrypt: &Encrypt,
+        app_conf: &AppConf,
         username: &str,
         password: &str,
+        validate_code: &str,
     ) -> Result<LoginForm> {
-        let key_data = format!(
-            "-----BEGIN PUBLIC KEY-----\n{}-----END PUBLIC KEY-----",
-            str_line_break(encrypt.data.pub_key.trim(), 64)
-        );
-        let username_encrypt = rsa_encrypt(key_data.as_str(), username)?;
-        let password_encrypt = rsa_encrypt(key_data.as_str(), password)?;
         Ok(LoginForm {
             app_key: APP_ID.to_string(),
             account_type: ACCOUNT_TYPE.to_string(),
-            validate_code: "".to_string(),
-            captcha_token: app_conf.captcha_token,
+            validate_code: validate_code.to_string(),
+            captcha_token: app_conf.captcha_token.clone(),
             dynamic_check: "FALSE".to_string(),
             client_type: "1".to_string(),
             cb_save_name: "3".to_string(),
             is_oauth2: false,
             return_url: RETURN_URL.to_string(),
-            param_id: app_conf.param_id,
-            user_name: format!("{}{}", encrypt.data.pre, username_encrypt),
-            password: format!("{}{}", encrypt.data.pre, password_encrypt),
+            param_id: app_conf.param_id.clone(),
+            user_name: encrypt_account(encrypt, username)?,
+            password: encrypt_account(encrypt, password)?,
         })
     }
 
-    /// 通过账号密码登录
+    /// 通过账号密码登录，需要验证码时调用`captcha_handler`，输错时重新获取验证码
     pub async fn login_by_password(&self, username: &str, password: &str) -> Result<TokenSession> {
         let encrypt = self.get_encrypt().await?;
         let app_conf = self.get_login_form().await?;
-        let data = self
-            .build_login_form(encrypt, app_conf.clone(), username, password)
+        let mut validate_code = String::new();
+        if self.need_captcha(&encrypt, username).await? {
+            validate_code = self.solve_captcha(&app_conf).await?;
+        }
+        let mut attempt = 1;
+        loop {
+            let data =
+                self.build_login_form(&encrypt, &app_conf, username, password, &validate_code)?;
+            match self.login_submit(&app_conf, &data).await {
+                Ok(res) => return self.get_session_for_pc(Some(res.to_url), None).await,
+                Err(err) if attempt < CAPTCHA_ATTEMPTS && is_captcha_error(&err) => {
+                    validate_code = self.solve_captcha(&app_conf).await?;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// 账号是否需要输入验证码，返回`1`时需要
+    async fn need_captcha(&self, encrypt: &Encrypt, username: &str) -> Result<bool> {
+        let res = self
+            .client
+            .post(format!(
+                "{}/api/logbox/oauth2/needcaptcha.do",
+                self.urls.auth
+            ))
+            .header(header::REFERER, HeaderValue::from_str(&self.urls.auth)?)
+            .form(&[
+                ("accountType", ACCOUNT_TYPE.to_string()),
+                ("userName", encrypt_account(encrypt, username)?),
+                ("appKey", APP_ID.to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
             .await?;
-        let login_response = self
+        Ok(res.trim() == "1")
+    }
+
+    /// 下载验证码图片并交给`captcha_handler`
+    async fn solve_captcha(&self, app_conf: &AppConf) -> Result<String> {
+        let image = self
             .client
-            .post("https://open.e.189.cn/api/logbox/oauth2/loginSubmit.do")
-            .header(header::REFERER, HeaderValue::from_static(AUTH_URL))
+            .get(format!(
+                "{}/api/logbox/oauth2/picCaptcha.do",
+                self.urls.auth
+            ))
+            .query(&[
+                ("token", app_conf.captcha_token.clone()),
+                ("REQID", app_conf.req_id.clone()),
+                ("rnd", util::timestamp().to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        // 默认在终端等待输入，不能阻塞异步线程
+        let handler = self.captcha_handler.clone();
+        let code = tokio::task::spawn_blocking(move || handler(&image)).await??;
+        if code.is_empty() {
+            return Err(Cloud189Error::CaptchaRequired("未输入验证码".to_string()).into());
+        }
+        Ok(code)
+    }
+
+    async fn login_submit(&self, app_conf: &AppConf, data: &LoginForm) -> Result<LoginResponse> {
+        let request = self
+            .client
+            .post(format!(
+                "{}/api/logbox/oauth2/loginSubmit.do",
+                self.urls.auth
+            ))
+            .header(header::REFERER, HeaderValue::from_str(&self.urls.auth)?)
             .header(
                 HeaderName::from_static("lt"),
                 HeaderValue::from_str(app_conf.lt.as_str())?,
@@ -206,15 +393,8 @@ impl CloudAuthClient {
                 HeaderName::from_static("reqid"),
                 HeaderValue::from_str(app_conf.req_id.as_str())?,
             )
-            .form(&data)
-            .send()
-            .await?
-            .json::<LoginResponse>()
-            .await?;
-        let res = self
-            .get_session_for_pc(Some(login_response.to_url), None)
-            .await?;
-        Ok(res)
+            .form(data);
+        self.send_json::<LoginResponse>(request).await
     }
 
     async fn get_session_for_pc(
@@ -239,78 +419,136 @@ impl CloudAuthClient {
         if let Some(token) = access_token {
             params["accessToken"] = serde_json::value::Value::String(token);
         }
-        let res = self
+        let request = self
             .client
-            .post("https://api.cloud.189.cn/getSessionForPC.action")
-            .query(&params)
-            .send()
-            .await?
-            .json::<TokenSession>()
-            .await?;
-        Ok(res)
+            .post(format!("{}/getSessionForPC.action", self.urls.api))
+            .query(&params);
+        self.send_json::<TokenSession>(request).await
     }
 
     /// 通过token登录
-    pub async fn _login_by_access_token(&self, access_token: &str) -> Result<TokenSession> {
+    pub async fn login_by_access_token(&self, access_token: &str) -> Result<TokenSession> {
         self.get_session_for_pc(None, Some(access_token.to_string()))
             .await
     }
 
-    //**
-    //    * sso登录
-    //    */
-    //   async loginBySsoCooike(cookie: string) {
-    //     logger.debug('loginBySsoCooike...')
-    //     const res = await this.request.get(`$`, {
-    //       searchParams: {
-    //         : AppID,
-    //         : ClientType,
-    //         : ,
-    //         : Date.now()
-    //       }
-    //     })
-    //     const redirect = await this.request(res.url, {
-    //       headers: {
-    //         Cookie: `SSON=${cookie}`
-    //       }
-    //     })
-    //     return await this.getSessionForPC({ redirectURL: redirect.url })
-    //   }
-
-    async fn login_by_sso_cookie(&self, cookie: &str) -> Result<()> {
-        let timestamp = util::timestamp();
-        self.client
-            .get(format!("{WEB_URL}/api/portal/unifyLoginForPC.action"))
+    /// 通过浏览器登录后的SSON cookie登录
+    pub async fn login_by_sso_cookie(&self, cookie: &str) -> Result<TokenSession> {
+        let res = self
+            .client
+            .get(format!(
+                "{}/api/portal/unifyLoginForPC.action",
+                self.urls.web
+            ))
             .query(&json!({
                 "appId": APP_ID,
                 "clientType": CLIENT_TYPE,
                 "returnURL": RETURN_URL,
-                "timeStamp": timestamp,
+                "timeStamp": util::timestamp(),
             }))
             .send()
-            .await?
-            .json::<serde_json::Value>()
             .await?;
-        Ok(())
+        // cookie有效时登录页会继续跳转到returnURL
+        let redirect = self
+            .client
+            .get(res.url().clone())
+            .header(header::COOKIE, format!("SSON={cookie}"))
+            .send()
+            .await?;
+        let redirect_url = redirect.url().to_string();
+        if redirect_url.starts_with(&self.urls.auth) {
+            bail!("SSON cookie无效或已过期");
+        }
+        self.get_session_for_pc(Some(redirect_url), None).await
+    }
+
+    /// 扫码登录，状态变化时调用`on_event`，由调用方展示二维码和提示
+    pub async fn login_by_qr_code(
+        &self,
+        mut on_event: impl FnMut(QrCodeEvent),
+    ) -> Result<TokenSession> {
+        let app_conf = self.get_login_form().await?;
+        let mut qr_code = self.get_qr_code().await?;
+        on_event(QrCodeEvent::Show(qr_code.uuid.clone()));
+        let mut scanned = false;
+        loop {
+            tokio::time::sleep(QR_CODE_POLL_INTERVAL).await;
+            match self.qr_code_state(&app_conf, &qr_code).await? {
+                QrCodeState::Waiting => {}
+                QrCodeState::Scanned => {
+                    if !scanned {
+                        on_event(QrCodeEvent::Scanned);
+                        scanned = true;
+                    }
+                }
+                QrCodeState::Expired => {
+                    qr_code = self.get_qr_code().await?;
+                    on_event(QrCodeEvent::Show(qr_code.uuid.clone()));
+                    scanned = false;
+                }
+                QrCodeState::Success(redirect_url) => {
+                    return self.get_session_for_pc(Some(redirect_url), None).await;
+                }
+                QrCodeState::Failed(status, msg) => bail!("扫码登录失败: {status} {msg}"),
+            }
+        }
+    }
+
+    async fn get_qr_code(&self) -> Result<QrCode> {
+        let request = self
+            .client
+            .post(format!("{}/api/logbox/oauth2/getUUID.do", self.urls.auth))
+            .form(&[("appId", APP_ID)]);
+        self.send_json::<QrCode>(request).await
     }
 
-    /// todo
-    /// 刷新token
-    async fn refresh_token(&self, refresh_token: &str) -> Result<()> {
+    async fn qr_code_state(&self, app_conf: &AppConf, qr_code: &QrCode) -> Result<QrCodeState> {
+        let timestamp = util::timestamp();
         let res = self
             .client
-            .post(format!("{AUTH_URL}/api/oauth2/refreshToken.do"))
+            .post(format!(
+                "{}/api/logbox/oauth2/qrcodeLoginState.do",
+                self.urls.auth
+            ))
+            .header(header::REFERER, HeaderValue::from_str(&self.urls.auth)?)
+            .header(
+                HeaderName::from_static("lt"),
+                HeaderValue::from_str(app_conf.lt.as_str())?,
+            )
+            .header(
+                HeaderName::from_static("reqid"),
+                HeaderValue::from_str(app_conf.req_id.as_str())?,
+            )
             .form(&json!({
-                "clientId": "",
-                "refreshToken": refresh_token,
-                "grantType": "refresh_token",
-                "format": "json",
+                "appId": APP_ID,
+                "clientType": CLIENT_TYPE,
+                "returnUrl": RETURN_URL,
+                "paramId": app_conf.param_id,
+                "uuid": qr_code.uuid,
+                "encryuuid": qr_code.encryuuid,
+                "date": Local::now().format("%Y-%m-%d%H:%M:%S").to_string(),
+                "timeStamp": timestamp,
             }))
             .send()
             .await?
-            .json::<serde_json::Value>()
+            .error_for_status()?
+            .json::<QrCodeStateResponse>()
             .await?;
-        Ok(())
+        Ok(res.into())
+    }
+
+    /// 用refresh token换取新的access token
+    pub async fn refresh_token(&self, refresh_token: &str) -> Result<AccessToken> {
+        let request = self
+            .client
+            .post(format!("{}/api/oauth2/refreshToken.do", self.urls.auth))
+            .form(&json!({
+                "clientId": APP_ID,
+                "refreshToken": refresh_token,
+                "grantType": "refresh_token",
+                "format": "json",
+            }));
+        self.send_json::<AccessToken>(request).await
     }
 }
 
@@ -318,10 +556,67 @@ fn str_line_break(s: &str, break_size: usize) -> String {
     s.chars()
         .collect::<Vec<_>>()
         .chunks(break_size)
-        .map(|chunk| format!("{}\n", chunk.into_iter().collect::<String>()))
+        .map(|chunk| format!("{}\n", chunk.iter().collect::<String>()))
         .collect()
 }
 
+/// 用登录配置中的公钥加密账号或密码，并加上前缀
+fn encrypt_account(encrypt: &Encrypt, value: &str) -> Result<String> {
+    let key_data = format!(
+        "-----BEGIN PUBLIC KEY-----\n{}-----END PUBLIC KEY-----",
+        str_line_break(encrypt.data.pub_key.trim(), 64)
+    );
+    Ok(format!(
+        "{}{}",
+        encrypt.data.pre,
+        rsa_encrypt(key_data.as_str(), value)?
+    ))
+}
+
+/// 验证码错误或缺少验证码，可以重新输入
+fn is_captcha_error(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<Cloud189Error>(),
+        Some(Cloud189Error::CaptchaRequired(_))
+    )
+}
+
+/// 默认的验证码处理，图片保存到临时目录后在终端输入
+pub fn prompt_captcha(image: &[u8]) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return Err(Cloud189Error::CaptchaRequired("无法在终端输入验证码".to_string()).into());
+    }
+    let path = save_captcha_image(image)?;
+    eprint!(
+        "登录需要验证码，图片已保存到{}，请输入验证码: ",
+        path.display()
+    );
+    let mut code = String::new();
+    let res = std::io::stderr()
+        .flush()
+        .and_then(|_| std::io::stdin().read_line(&mut code));
+    let _ = std::fs::remove_file(&path);
+    res?;
+    Ok(code.trim().to_string())
+}
+
+/// 在临时目录新建只有当前用户可读写的文件，文件名随机，已存在时不会覆盖或跟随链接
+fn save_captcha_image(image: &[u8]) -> Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!(
+        "cloud-189-captcha-{:016x}.png",
+        rand::random::<u64>()
+    ));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&path)?.write_all(image)?;
+    Ok(path)
+}
+
 fn rsa_encrypt(public_key: &str, orig_data: &str) -> Result<String> {
     let rsa_public_key = RsaPublicKey::from_public_key_pem(public_key)?;
     let encrypted = rsa_public_key.encrypt(
@@ -331,3 +626,211 @@ fn rsa_encrypt(public_key: &str, orig_data: &str) -> Result<String> {
     )?;
     Ok(hex::encode_upper(&encrypted))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockCloud};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wiremock::matchers::{body_string_contains, header, method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_login_by_password() -> Result<()> {
+        let mock = MockCloud::start().await;
+        let token = mock
+            .auth_client()
+            .login_by_password("user", "password")
+            .await?;
+        assert_eq!(token.session_key, mock::SESSION_KEY);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_by_password_with_captcha() -> Result<()> {
+        let mock = MockCloud::start().await;
+        Mock::given(path("/auth/api/logbox/oauth2/needcaptcha.do"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("1"))
+            .with_priority(1)
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/auth/api/logbox/oauth2/picCaptcha.do"))
+            .and(query_param("token", "mock-captcha-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"captcha".to_vec()))
+            .mount(&mock.server)
+            .await;
+        // 只有验证码1234能登录
+        Mock::given(path("/auth/api/logbox/oauth2/loginSubmit.do"))
+            .and(body_string_contains("validateCode=0000"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"result": -2, "msg": "图形验证码错误"})),
+            )
+            .with_priority(1)
+            .mount(&mock.server)
+            .await;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let client = CloudAuthClient::try_new()?
+            .with_base_urls(mock.urls())
+            .with_captcha_handler({
+                let calls = calls.clone();
+                move |image| {
+                    assert_eq!(image, b"captcha");
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 => Ok("0000".to_string()),
+                        _ => Ok("1234".to_string()),
+                    }
+                }
+            });
+        let token = client.login_by_password("user", "password").await?;
+        assert_eq!(token.session_key, mock::SESSION_KEY);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    /// 登录页跳转到统一认证页，带有效的SSON cookie时认证页再跳转回`callback`
+    async fn mount_sso(mock: &MockCloud) -> String {
+        let login_page = format!(
+            "{}/api/logbox/oauth2/unifyAccountLogin.do",
+            mock.urls().auth
+        );
+        let callback = format!("{}/callback?code=1", mock.urls().web);
+        Mock::given(method("GET"))
+            .and(path("/web/api/portal/unifyLoginForPC.action"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", login_page.as_str()))
+            .with_priority(1)
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/auth/api/logbox/oauth2/unifyAccountLogin.do"))
+            .and(header("cookie", "SSON=valid"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", callback.as_str()))
+            .with_priority(1)
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/auth/api/logbox/oauth2/unifyAccountLogin.do"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html>login</html>"))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/web/callback"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock.server)
+            .await;
+        callback
+    }
+
+    #[tokio::test]
+    async fn test_login_by_sso_cookie() -> Result<()> {
+        let mock = MockCloud::start().await;
+        let callback = mount_sso(&mock).await;
+        Mock::given(method("POST"))
+            .and(path("/api/getSessionForPC.action"))
+            .and(query_param("redirectURL", callback.as_str()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(mock::token_session()))
+            .with_priority(1)
+            .expect(1)
+            .mount(&mock.server)
+            .await;
+        let token = mock.auth_client().login_by_sso_cookie("valid").await?;
+        assert_eq!(token.session_key, mock::SESSION_KEY);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_by_sso_cookie_invalid() {
+        let mock = MockCloud::start().await;
+        mount_sso(&mock).await;
+        let Err(err) = mock.auth_client().login_by_sso_cookie("expired").await else {
+            panic!("无效的cookie不应该登录成功");
+        };
+        assert!(err.to_string().contains("SSON"), "{err}");
+        assert_eq!(mock.request_count("/api/getSessionForPC.action").await, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_login_by_qr_code() -> Result<()> {
+        let mock = MockCloud::start().await;
+        Mock::given(path("/auth/api/logbox/oauth2/getUUID.do"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"uuid": "qr", "encryuuid": "e"})),
+            )
+            .mount(&mock.server)
+            .await;
+        // 扫码后手机确认前会多次返回已扫码
+        for status in [-106, -11002, -11002] {
+            Mock::given(path("/auth/api/logbox/oauth2/qrcodeLoginState.do"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({"status": status})))
+                .up_to_n_times(1)
+                .mount(&mock.server)
+                .await;
+        }
+        Mock::given(path("/auth/api/logbox/oauth2/qrcodeLoginState.do"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": 0,
+                "redirectUrl": format!("{}/callback", mock.urls().web),
+            })))
+            .mount(&mock.server)
+            .await;
+
+        let mut events = Vec::new();
+        let token = mock
+            .auth_client()
+            .login_by_qr_code(|event| events.push(event))
+            .await?;
+        assert_eq!(token.session_key, mock::SESSION_KEY);
+        assert_eq!(
+            events,
+            [QrCodeEvent::Show("qr".to_string()), QrCodeEvent::Scanned]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_captcha_image() -> Result<()> {
+        let path = save_captcha_image(b"png")?;
+        assert_eq!(std::fs::read(&path)?, b"png");
+        let other = save_captcha_image(b"png")?;
+        assert_ne!(other, path);
+        std::fs::remove_file(other)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_captcha_error() {
+        let err = anyhow::Error::from(Cloud189Error::from_login_result(-2, "图形验证码错误"));
+        assert!(is_captcha_error(&err));
+        let err = anyhow::Error::from(Cloud189Error::from_login_result(-2, "密码错误"));
+        assert!(!is_captcha_error(&err));
+        assert!(!is_captcha_error(&anyhow!("network error")));
+    }
+
+    #[test]
+    fn test_qr_code_state() -> Result<()> {
+        let state = |body: &str| -> Result<QrCodeState> {
+            Ok(serde_json::from_str::<QrCodeStateResponse>(body)?.into())
+        };
+        assert_eq!(state(r#"{"status":-106}"#)?, QrCodeState::Waiting);
+        assert_eq!(state(r#"{"status":-11002}"#)?, QrCodeState::Scanned);
+        assert_eq!(state(r#"{"status":-11001}"#)?, QrCodeState::Expired);
+        assert_eq!(
+            state(r#"{"status":0,"redirectUrl":"https://cloud.189.cn/callback"}"#)?,
+            QrCodeState::Success("https://cloud.189.cn/callback".to_string())
+        );
+        assert_eq!(
+            state(r#"{"status":-1,"msg":"error"}"#)?,
+            QrCodeState::Failed(-1, "error".to_string())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+use crate::client::CloudClient;
+use crate::error::Cloud189Error;
+use crate::file::{CloudFile, CloudFolder, Entry, Space};
+use crate::util;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// 查询批量任务状态的间隔
+const BATCH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// 等待批量任务完成的最长时间
+const BATCH_TASK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// 批量任务类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskType {
+    Copy,
+    Move,
+    /// 删除到回收站
+    Delete,
+    /// 从回收站还原
+    Restore,
+    /// 从回收站彻底删除
+    ClearRecycle,
+    /// 清空回收站
+    EmptyRecycle,
+    /// 转存分享的文件
+    ShareSave,
+}
+
+impl TaskType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TaskType::Copy => "COPY",
+            TaskType::Move => "MOVE",
+            TaskType::Delete => "DELETE",
+            TaskType::Restore => "RESTORE",
+            TaskType::ClearRecycle => "CLEAR_RECYCLE",
+            TaskType::EmptyRecycle => "EMPTY_RECYCLE",
+            TaskType::ShareSave => "SHARE_SAVE",
+        }
+    }
+}
+
+/// 批量任务中的一个文件或文件夹
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskInfo {
+    pub file_id: String,
+    pub file_name: String,
+    /// 文件夹为1
+    pub is_folder: u8,
+}
+
+impl From<&CloudFile> for TaskInfo {
+    fn from(file: &CloudFile) -> Self {
+        Self {
+            file_id: file.id.clone(),
+            file_name: file.name.clone(),
+            is_folder: 0,
+        }
+    }
+}
+
+impl From<&CloudFolder> for TaskInfo {
+    fn from(folder: &CloudFolder) -> Self {
+        Self {
+            file_id: folder.id.clone(),
+            file_name: folder.name.clone(),
+            is_folder: 1,
+        }
+    }
+}
+
+impl From<&Entry> for TaskInfo {
+    fn from(entry: &Entry) -> Self {
+        match entry {
+            Entry::File(file) => file.into(),
+            Entry::Folder(folder) => folder.into(),
+        }
+    }
+}
+
+/// 批量任务执行结果
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchTaskResult {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub task_id: String,
+    /// 1: 初始化，2: 存在冲突，3: 执行中，4: 完成
+    pub task_status: i32,
+    #[serde(default)]
+    pub successed_count: u64,
+    #[serde(default)]
+    pub failed_count: u64,
+    #[serde(default)]
+    pub skip_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateBatchTaskResponse {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    task_id: String,
+}
+
+impl BatchTaskResult {
+    /// 所有文件都处理成功
+    pub fn is_success(&self) -> bool {
+        self.failed_count == 0
+    }
+}
+
+impl CloudClient {
+    /// 复制文件或文件夹到`target_folder_id`
+    pub async fn copy_items(
+        &self,
+        space: &Space,
+        items: &[TaskInfo],
+        target_folder_id: &str,
+    ) -> Result<BatchTaskResult> {
+        let mut params = space.params();
+        params.push(("targetFolderId", target_folder_id.to_string()));
+        self.run_batch_task(TaskType::Copy, items, &params).await
+    }
+
+    /// 移动文件或文件夹到`target_folder_id`
+    pub async fn move_items(
+        &self,
+        space: &Space,
+        items: &[TaskInfo],
+        target_folder_id: &str,
+    ) -> Result<BatchTaskResult> {
+        let mut params = space.params();
+        params.push(("targetFolderId", target_folder_id.to_string()));
+        self.run_batch_task(TaskType::Move, items, &params).await
+    }
+
+    /// 删除文件或文件夹到回收站
+    pub async fn delete_items(&self, space: &Space, items: &[TaskInfo]) -> Result<BatchTaskResult> {
+        self.run_batch_task(TaskType::Delete, items, &space.params())
+            .await
+    }
+
+    /// 创建批量任务并等待完成，`params`为任务类型需要的其他参数
+    pub(crate) async fn run_batch_task(
+        &self,
+        task_type: TaskType,
+        task_infos: &[TaskInfo],
+        params: &[(&str, String)],
+    ) -> Result<BatchTaskResult> {
+        let task_id = self
+            .create_batch_task(task_type, task_infos, params)
+            .await?;
+        self.wait_batch_task(task_type, &task_id, BATCH_TASK_TIMEOUT)
+            .await
+    }
+
+    async fn create_batch_task(
+        &self,
+        task_type: TaskType,
+        task_infos: &[TaskInfo],
+        params: &[(&str, String)],
+    ) -> Result<String> {
+        let mut form = vec![
+            ("type", task_type.as_str().to_string()),
+            ("taskInfos", serde_json::to_string(task_infos)?),
+        ];
+        form.extend_from_slice(params);
+        let request = self
+            .client
+            .post(format!(
+                "{}/api/open/batch/createBatchTask.action",
+                self.urls.web
+            ))
+            .form(&form);
+        let res = self.send_json::<CreateBatchTaskResponse>(request).await?;
+        Ok(res.task_id)
+    }
+
+    /// 等待任务完成，超过`timeout`返回错误
+    async fn wait_batch_task(
+        &self,
+        task_type: TaskType,
+        task_id: &str,
+        timeout: Duration,
+    ) -> Result<BatchTaskResult> {
+        tokio::time::timeout(timeout, self.poll_batch_task(task_type, task_id))
+            .await
+            .map_err(|_| anyhow!("批量任务{task_id}在{timeout:?}内没有完成"))?
+    }
+
+    /// 轮询任务状态，只有初始化和执行中会继续等待
+    async fn poll_batch_task(&self, task_type: TaskType, task_id: &str) -> Result<BatchTaskResult> {
+        loop {
+            let request = self
+                .client
+                .post(format!(
+                    "{}/api/open/batch/checkBatchTask.action",
+                    self.urls.web
+                ))
+                .form(&[("type", task_type.as_str()), ("taskId", task_id)]);
+            let res = self.send_json::<BatchTaskResult>(request).await?;
+            match res.task_status {
+                4 => return Ok(res),
+                1 | 3 => tokio::time::sleep(BATCH_POLL_INTERVAL).await,
+                2 => {
+                    return Err(Cloud189Error::AlreadyExists(format!(
+                        "批量任务{task_id}存在同名文件冲突"
+                    ))
+                    .into());
+                }
+                status => {
+                    return Err(Cloud189Error::Api {
+                        code: status.to_string(),
+                        message: format!("批量任务{task_id}状态异常"),
+                    }
+                    .into());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::const_val::ROOT_FOLDER_ID;
+    use crate::mock::MockCloud;
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, ResponseTemplate};
+
+    fn items() -> Vec<TaskInfo> {
+        vec![TaskInfo {
+            file_id: "1".to_string(),
+            file_name: "a.txt".to_string(),
+            is_folder: 0,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_wait_batch_task() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mock.mount_batch_task("DELETE", &[1, 3, 4]).await;
+        let res = mock
+            .client()
+            .delete_items(&Space::Personal, &items())
+            .await?;
+        assert_eq!(res.task_id, "42");
+        assert!(res.is_success());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_batch_task_failed_status() {
+        let mock = MockCloud::start().await;
+        mock.mount_batch_task("DELETE", &[3, -1]).await;
+        let err = mock
+            .client()
+            .delete_items(&Space::Personal, &items())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Cloud189Error>(),
+            Some(Cloud189Error::Api { code, .. }) if code == "-1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wait_batch_task_timeout() -> Result<()> {
+        let mock = MockCloud::start().await;
+        Mock::given(method("POST"))
+            .and(path("/web/api/open/batch/checkBatchTask.action"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "res_code": 0,
+                "res_message": "成功",
+                "taskId": "42",
+                "taskStatus": 3,
+            })))
+            .mount(&mock.server)
+            .await;
+        mock.mount_list_files(ROOT_FOLDER_ID, json!([]), json!([]))
+            .await;
+        let client = mock.client();
+        // 先完成登录，超时只计算轮询
+        client.list_all(&Space::Personal, ROOT_FOLDER_ID).await?;
+        let err = client
+            .wait_batch_task(TaskType::Delete, "42", Duration::from_millis(1500))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("没有完成"), "{err}");
+        Ok(())
+    }
+}
@@ -0,0 +1,431 @@
+use crate::client::CloudClient;
+use crate::config::Account;
+use crate::error::Cloud189Error;
+use crate::session::SessionStore;
+use crate::util;
+use anyhow::Result;
+use chrono::{NaiveDateTime, NaiveTime, TimeDelta};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// 每日抽奖任务，`(taskId, activityId)`
+const LOTTERY_TASKS: [(&str, &str); 3] = [
+    ("TASK_SIGNIN", "ACT_SIGNIN"),
+    ("TASK_SIGNIN_PHOTOS", "ACT_SIGNIN"),
+    ("TASK_2022_FLDFS_KJ", "ACT_SIGNIN"),
+];
+
+/// 签到结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignResult {
+    /// 今天已经签到过
+    pub already_signed: bool,
+    /// 获得的空间，单位MB
+    pub bonus: u64,
+}
+
+/// 抽奖结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LotteryResult {
+    /// 抽中的奖品
+    Prize(String),
+    /// 今天的抽奖次数已用完
+    NoChance,
+    /// 抽奖失败不影响其他任务
+    Failed(String),
+}
+
+/// 家庭云签到结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FamilySignResult {
+    Signed {
+        family_id: String,
+        sign: SignResult,
+    },
+    /// 签到失败不影响其他家庭云，获取家庭云列表失败时没有`family_id`
+    Failed {
+        family_id: Option<String>,
+        error: String,
+    },
+}
+
+/// 一个账号的每日任务结果
+#[derive(Debug, Clone)]
+pub struct DailyReport {
+    pub sign: SignResult,
+    pub lottery: Vec<LotteryResult>,
+    pub families: Vec<FamilySignResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserSignResponse {
+    is_sign: bool,
+    #[serde(default)]
+    netdisk_bonus: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DrawPrizeResponse {
+    #[serde(default)]
+    prize_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FamilySignResponse {
+    #[serde(default)]
+    bonus_space: u64,
+    /// 1: 今天已签到
+    #[serde(default)]
+    sign_status: i32,
+}
+
+impl CloudClient {
+    /// 个人云每日签到
+    pub async fn user_sign(&self) -> Result<SignResult> {
+        let request = self
+            .client
+            .get(format!("{}/mkt/userSign.action", self.urls.web))
+            .query(&[
+                ("rand", util::timestamp().to_string()),
+                ("clientType", "TELEANDROID".to_string()),
+                ("version", "8.6.3".to_string()),
+                ("model", "SM-G930K".to_string()),
+            ]);
+        let res = self.send_json::<UserSignResponse>(request).await?;
+        Ok(SignResult {
+            already_signed: res.is_sign,
+            bonus: res.netdisk_bonus,
+        })
+    }
+
+    /// 每日抽奖，次数用完时返回`NoChance`
+    pub async fn draw_prize(&self, task_id: &str, activity_id: &str) -> Result<LotteryResult> {
+        let request = self
+            .client
+            .get(format!(
+                "{}/v2/drawPrizeMarketDetails.action",
+                self.urls.m_web
+            ))
+            .query(&[("taskId", task_id), ("activityId", activity_id)]);
+        match self.send_json::<DrawPrizeResponse>(request).await {
+            Ok(res) => Ok(LotteryResult::Prize(res.prize_name)),
+            Err(err) => match err.downcast_ref::<Cloud189Error>() {
+                Some(Cloud189Error::Api { code, .. }) if code == "User_Not_Chance" => {
+                    Ok(LotteryResult::NoChance)
+                }
+                _ => Err(err),
+            },
+        }
+    }
+
+    /// 家庭云每日签到
+    pub async fn family_sign(&self, family_id: &str) -> Result<SignResult> {
+        let request = self
+            .client
+            .get(format!(
+                "{}/open/family/manage/exeFamilyUserSign.action",
+                self.urls.api
+            ))
+            .query(&[("familyId", family_id)]);
+        let res = self.send_json::<FamilySignResponse>(request).await?;
+        Ok(SignResult {
+            already_signed: res.sign_status == 1,
+            bonus: res.bonus_space,
+        })
+    }
+
+    /// 签到、抽奖，`with_family`时同时签到加入的所有家庭云
+    pub async fn daily_tasks(&self, with_family: bool) -> Result<DailyReport> {
+        let sign = self.user_sign().await?;
+        let mut lottery = Vec::new();
+        for (task_id, activity_id) in LOTTERY_TASKS {
+            let res = self.draw_prize(task_id, activity_id).await;
+            lottery.push(res.unwrap_or_else(|err| LotteryResult::Failed(err.to_string())));
+        }
+        let mut families = Vec::new();
+        if with_family {
+            match self.get_family_list().await {
+                Ok(list) => {
+                    for family in list {
+                        families.push(match self.family_sign(&family.id).await {
+                            Ok(sign) => FamilySignResult::Signed {
+                                family_id: family.id,
+                                sign,
+                            },
+                            Err(err) => FamilySignResult::Failed {
+                                family_id: Some(family.id),
+                                error: err.to_string(),
+                            },
+                        });
+                    }
+                }
+                Err(err) => families.push(FamilySignResult::Failed {
+                    family_id: None,
+                    error: err.to_string(),
+                }),
+            }
+        }
+        Ok(DailyReport {
+            sign,
+            lottery,
+            families,
+        })
+    }
+}
+
+/// 依次执行每个账号的每日任务，会话保存在默认位置，一个账号失败不影响其他账号
+pub async fn run_accounts(
+    accounts: &[Account],
+    with_family: bool,
+) -> Vec<(String, Result<DailyReport>)> {
+    let mut results = Vec::new();
+    for account in accounts {
+        let res = async {
+            let mut client = CloudClient::try_new(&account.username, &account.password)?;
+            if let Some(path) = SessionStore::default_path(&account.username) {
+                client = client.with_store(SessionStore::new(path));
+            }
+            client.daily_tasks(with_family).await
+        }
+        .await;
+        results.push((account.username.clone(), res));
+    }
+    results
+}
+
+/// 多个账号的签到汇总
+pub fn format_report(results: &[(String, Result<DailyReport>)]) -> String {
+    let mut text = String::from("[cloud-189] 每日签到");
+    for (username, res) in results {
+        let _ = write!(text, "\n{username}: ");
+        let report = match res {
+            Ok(report) => report,
+            Err(err) => {
+                let _ = write!(text, "失败 {err}");
+                continue;
+            }
+        };
+        text.push_str(&format_sign(&report.sign));
+        for res in &report.lottery {
+            match res {
+                LotteryResult::Prize(prize) => {
+                    let _ = write!(text, "，抽奖获得{prize}");
+                }
+                LotteryResult::NoChance => text.push_str("，抽奖次数已用完"),
+                LotteryResult::Failed(err) => {
+                    let _ = write!(text, "，抽奖失败 {err}");
+                }
+            }
+        }
+        for res in &report.families {
+            match res {
+                FamilySignResult::Signed { family_id, sign } => {
+                    let _ = write!(text, "，家庭云{family_id}{}", format_sign(sign));
+                }
+                FamilySignResult::Failed {
+                    family_id: Some(family_id),
+                    error,
+                } => {
+                    let _ = write!(text, "，家庭云{family_id}签到失败 {error}");
+                }
+                FamilySignResult::Failed {
+                    family_id: None,
+                    error,
+                } => {
+                    let _ = write!(text, "，获取家庭云失败 {error}");
+                }
+            }
+        }
+    }
+    text
+}
+
+fn format_sign(sign: &SignResult) -> String {
+    if sign.already_signed {
+        "今天已签到".to_string()
+    } else {
+        format!("签到获得{}M空间", sign.bonus)
+    }
+}
+
+/// 距离下一次`at`的时间，今天已经过了时为明天
+pub fn until_next(now: NaiveDateTime, at: NaiveTime) -> Duration {
+    let mut next = now.date().and_time(at);
+    if next <= now {
+        next += TimeDelta::days(1);
+    }
+    (next - now).to_std().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockCloud};
+    use anyhow::anyhow;
+    use serde_json::json;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+
+    /// 签到、三次抽奖分别为中奖、次数用完和出错，两个家庭云中一个签到出错
+    async fn mount_daily_tasks(mock: &MockCloud) {
+        Mock::given(method("GET"))
+            .and(path("/web/mkt/userSign.action"))
+            .and(query_param("sessionKey", mock::SESSION_KEY))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "userSignId": null,
+                "userId": 1,
+                "signTime": "",
+                "netdiskBonus": 50,
+                "isSign": false,
+            })))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/m/v2/drawPrizeMarketDetails.action"))
+            .and(query_param("taskId", "TASK_SIGNIN"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "prizeName": "天翼云盘50M空间",
+            })))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/m/v2/drawPrizeMarketDetails.action"))
+            .and(query_param("taskId", "TASK_SIGNIN_PHOTOS"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "errorCode": "User_Not_Chance",
+            })))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/m/v2/drawPrizeMarketDetails.action"))
+            .and(query_param("taskId", "TASK_2022_FLDFS_KJ"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("busy"))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/open/family/manage/getFamilyList.action"))
+            .and(header("accesstoken", mock::API_ACCESS_TOKEN))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "familyInfoResp": [{"familyId": 1}, {"familyId": 2}],
+            })))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/open/family/manage/exeFamilyUserSign.action"))
+            .and(query_param("familyId", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "bonusSpace": 20,
+                "signFamilyId": 1,
+                "signStatus": 1,
+                "signTime": "",
+            })))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/open/family/manage/exeFamilyUserSign.action"))
+            .and(query_param("familyId", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "res_code": "FamilyOperationFailed",
+                "res_message": "签到失败",
+            })))
+            .mount(&mock.server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_daily_tasks() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mount_daily_tasks(&mock).await;
+        let client = mock.client();
+
+        assert_eq!(
+            client.user_sign().await?,
+            SignResult {
+                already_signed: false,
+                bonus: 50,
+            }
+        );
+        assert_eq!(
+            client
+                .draw_prize("TASK_SIGNIN_PHOTOS", "ACT_SIGNIN")
+                .await?,
+            LotteryResult::NoChance
+        );
+        assert_eq!(
+            client.family_sign("1").await?,
+            SignResult {
+                already_signed: true,
+                bonus: 20,
+            }
+        );
+
+        // 抽奖和家庭云签到出错时仍返回其他任务的结果
+        let report = client.daily_tasks(true).await?;
+        assert!(matches!(report.lottery[2], LotteryResult::Failed(_)));
+        assert!(matches!(
+            &report.families[1],
+            FamilySignResult::Failed { family_id: Some(id), .. } if id == "2"
+        ));
+        let results = vec![
+            ("a".to_string(), Ok(report)),
+            ("b".to_string(), Err(anyhow!("会话已失效"))),
+        ];
+        let text = format_report(&results);
+        assert!(text.starts_with(
+            "[cloud-189] 每日签到\n\
+             a: 签到获得50M空间，抽奖获得天翼云盘50M空间，抽奖次数已用完，抽奖失败 "
+        ));
+        assert!(text.ends_with(
+            "，家庭云1今天已签到，家庭云2签到失败 接口返回错误: FamilyOperationFailed 签到失败\n\
+             b: 失败 会话已失效"
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_daily_tasks_family_list_failed() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mount_daily_tasks(&mock).await;
+        Mock::given(method("GET"))
+            .and(path("/api/open/family/manage/getFamilyList.action"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("busy"))
+            .with_priority(1)
+            .mount(&mock.server)
+            .await;
+        let report = mock.client().daily_tasks(true).await?;
+        assert_eq!(report.sign.bonus, 50);
+        assert!(matches!(
+            report.families[..],
+            [FamilySignResult::Failed {
+                family_id: None,
+                ..
+            }]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_until_next() {
+        let at = NaiveTime::from_hms_opt(8, 30, 0).unwrap();
+        let now = |time: &str| {
+            NaiveDateTime::parse_from_str(&format!("2024-05-01 {time}"), "%Y-%m-%d %H:%M:%S")
+                .unwrap()
+        };
+        assert_eq!(
+            until_next(now("08:00:00"), at),
+            Duration::from_secs(30 * 60)
+        );
+        assert_eq!(
+            until_next(now("08:30:00"), at),
+            Duration::from_secs(24 * 60 * 60)
+        );
+        assert_eq!(
+            until_next(now("09:30:00"), at),
+            Duration::from_secs(23 * 60 * 60)
+        );
+    }
+}
@@ -1,15 +1,24 @@
 use crate::auth::CloudAuthClient;
 use crate::const_val::*;
+use crate::error::{self, ResponseStatus};
+use crate::session::{SavedSession, SessionStore};
 use crate::util;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{header, Client, Method, Request, RequestBuilder};
+use reqwest::{header, Client, Request, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use tokio::sync::Mutex;
+use url::Url;
 
 pub struct CloudClient {
-    username: String,
-    password: String,
-    client: Client,
-    auth_client: CloudAuthClient,
+    pub(crate) username: String,
+    pub(crate) password: String,
+    pub(crate) client: Client,
+    pub(crate) auth_client: CloudAuthClient,
+    /// 当前会话，首次请求时建立
+    pub(crate) session: Mutex<Option<SavedSession>>,
+    pub(crate) store: Option<SessionStore>,
+    pub(crate) urls: BaseUrls,
 }
 
 impl CloudClient {
@@ -30,33 +39,86 @@ impl CloudClient {
             password: password.to_string(),
             client,
             auth_client: CloudAuthClient::try_new()?,
+            session: Mutex::new(None),
+            store: None,
+            urls: BaseUrls::default(),
         })
     }
 
-    /// 获取用户网盘存储容量信息
-    pub async fn get_user_size_info(&self) -> Result<()> {
-        let request = self
-            .client
-            .get(format!("{WEB_URL}/api/portal/getUserSizeInfo.action"))
-            .build()?;
+    /// 替换接口地址，登录接口同时替换
+    pub fn with_base_urls(mut self, urls: BaseUrls) -> Self {
+        self.auth_client = self.auth_client.with_base_urls(urls.clone());
+        self.urls = urls;
+        self
+    }
 
-        let res = self
-            .client
-            .execute(self.before_request(request)?)
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
+    /// 登录接口，用于密码以外的登录方式
+    pub fn auth_client(&self) -> &CloudAuthClient {
+        &self.auth_client
+    }
 
-        println!("{}", res.to_string());
-        Ok(())
+    /// 密码登录需要验证码时调用`handler`，默认在终端输入
+    pub fn with_captcha_handler(
+        mut self,
+        handler: impl Fn(&[u8]) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.auth_client = self.auth_client.with_captcha_handler(handler);
+        self
+    }
+
+    /// 从会话文件恢复会话，会话变化时写回
+    pub fn with_store(mut self, store: SessionStore) -> Self {
+        self.store = Some(store);
+        self
     }
 
-    fn before_request(&self, request: Request) -> Result<Request> {
+    /// 签名后发送请求并解析json，会话失效时重新建立会话并重试一次，接口错误返回`Cloud189Error`
+    pub(crate) async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T> {
+        let saved = self.current_session().await?;
+        let retry = request.try_clone();
+        let (mut status, mut body) = self.execute(request, &saved).await?;
+        if let Some(retry) = retry.filter(|_| is_session_error(&body)) {
+            self.renew_session(&saved.token.session_key).await?;
+            let saved = self.current_session().await?;
+            (status, body) = self.execute(retry, &saved).await?;
+        }
+        Ok(error::parse_response(status, &body)?)
+    }
+
+    /// 用指定会话签名后发送请求，不处理会话失效
+    pub(crate) async fn execute(
+        &self,
+        request: RequestBuilder,
+        saved: &SavedSession,
+    ) -> Result<(StatusCode, Vec<u8>)> {
+        let request = self.before_request(request.build()?, saved)?;
+        let response = self.client.execute(request).await?;
+        let status = response.status();
+        Ok((status, response.bytes().await?.to_vec()))
+    }
+
+    fn before_request(&self, request: Request, saved: &SavedSession) -> Result<Request> {
         let url = request.url().as_str();
-        if url.starts_with(API_URL) {
-            self.api_url_header(request, "")
-        } else if url.starts_with(WEB_URL) {
-            self.web_url_handle(request, "")
+        if url.starts_with(&self.urls.api) {
+            self.api_url_header(request, &saved.api_access_token)
+        } else if url.starts_with(&self.urls.web) || url.starts_with(&self.urls.m_web) {
+            self.web_url_handle(request, &saved.token.session_key)
+        } else if url.starts_with(&format!("{}/family", self.urls.upload)) {
+            // 家庭云上传使用单独的会话
+            self.upload_url_handle(
+                request,
+                &saved.token.family_session_key,
+                &saved.token.family_session_secret,
+            )
+        } else if url.starts_with(&self.urls.upload) {
+            self.upload_url_handle(
+                request,
+                &saved.token.session_key,
+                &saved.token.session_secret,
+            )
         } else {
             Ok(request)
         }
@@ -64,11 +126,7 @@ impl CloudClient {
 
     fn api_url_header(&self, request: Request, access_token: &str) -> Result<Request> {
         let timestamp = util::timestamp().to_string();
-        let mut params = if Method::GET == request.method().clone() {
-            util::parse_url_params(request.url())
-        } else {
-            util::parse_json_request_body(&request)
-        };
+        let mut params = util::parse_request_params(&request);
         params.insert("Timestamp".to_string(), timestamp.clone());
         params.insert("AccessToken".to_string(), access_token.to_string());
         let signature = util::get_signature(params);
@@ -97,11 +155,7 @@ impl CloudClient {
 
     fn web_url_handle(&self, request: Request, session_key: &str) -> Result<Request> {
         let header = if request.url().as_str().contains("/open") {
-            let mut params = if Method::GET == request.method() {
-                util::parse_url_params(request.url())
-            } else {
-                util::parse_json_request_body(&request)
-            };
+            let mut params = util::parse_request_params(&request);
             let timestamp = util::timestamp().to_string();
             params.insert("Timestamp".to_string(), timestamp.clone());
             params.insert("AppKey".to_string(), "600100422".to_string());
@@ -127,14 +181,218 @@ impl CloudClient {
         } else {
             HeaderMap::new()
         };
-        // url_params.insert("sessionKey".to_string(), session_key.to_string());
-        // RequestBuilder::from_parts(self.client.clone(), request)
-        //     .headers(header)
-        //     .build()?
+        let mut request = RequestBuilder::from_parts(self.client.clone(), request)
+            .headers(header)
+            .build()?;
+        set_query_param(request.url_mut(), "sessionKey", session_key);
+        Ok(request)
+    }
+
+    /// 上传接口的参数用session secret加密，并用HMAC-SHA1签名
+    fn upload_url_handle(
+        &self,
+        request: Request,
+        session_key: &str,
+        session_secret: &str,
+    ) -> Result<Request> {
+        let key = session_secret
+            .as_bytes()
+            .get(..16)
+            .ok_or_else(|| anyhow!("session secret无效"))?;
+        let params = util::aes_ecb_encrypt_hex(request.url().query().unwrap_or_default(), key)?;
+        let mut url = request.url().clone();
+        url.set_query(None);
+        url.query_pairs_mut().append_pair("params", &params);
+        let date = util::http_date();
+        let signature = util::hmac_sha1_hex(
+            session_secret.as_bytes(),
+            &format!(
+                "SessionKey={}&Operate={}&RequestURI={}&Date={}&params={}",
+                session_key,
+                request.method(),
+                url.path(),
+                date,
+                params
+            ),
+        );
+
+        let mut header = HeaderMap::new();
+        header.insert(header::DATE, HeaderValue::from_str(date.as_str())?);
+        header.insert(
+            HeaderName::from_static("sessionkey"),
+            HeaderValue::from_str(session_key)?,
+        );
+        header.insert(
+            HeaderName::from_static("signature"),
+            HeaderValue::from_str(signature.as_str())?,
+        );
+        header.insert(
+            HeaderName::from_static("x-request-id"),
+            HeaderValue::from_str(util::request_id().as_str())?,
+        );
         Ok(self
             .client
-            .request(request.method().clone(), "")
+            .request(request.method().clone(), url)
             .headers(header)
             .build()?)
     }
 }
+
+/// 设置url参数，已有同名参数时替换
+fn set_query_param(url: &mut Url, key: &str, value: &str) {
+    let pairs = url
+        .query_pairs()
+        .filter(|(name, _)| name != key)
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair(key, value);
+}
+
+/// 接口通过`errorCode`、`res_code`或`code`返回会话失效
+fn is_session_error(body: &[u8]) -> bool {
+    serde_json::from_slice::<ResponseStatus>(body)
+        .is_ok_and(|res| res.check().is_err_and(|err| err.is_session_expired()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::Space;
+    use crate::mock::{self, MockCloud};
+    use serde_json::json;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+
+    fn saved_session() -> SavedSession {
+        let token = serde_json::from_value(mock::token_session()).unwrap();
+        SavedSession {
+            api_access_token: mock::API_ACCESS_TOKEN.to_string(),
+            ..SavedSession::new("user", token)
+        }
+    }
+
+    #[test]
+    fn test_before_request() -> Result<()> {
+        let client = CloudClient::try_new("user", "")?;
+        let saved = saved_session();
+
+        let request = client
+            .client
+            .get(format!("{API_URL}/open/file/listFiles.action?folderId=-11"))
+            .build()?;
+        let request = client.before_request(request, &saved)?;
+        assert_eq!(request.headers()["accesstoken"], "api-token");
+        assert_eq!(request.headers()["sign-type"], "1");
+
+        let request = client
+            .client
+            .get(format!(
+                "{WEB_URL}/api/portal/getUserSizeInfo.action?sessionKey=old&noCache=1"
+            ))
+            .build()?;
+        let request = client.before_request(request, &saved)?;
+        assert_eq!(
+            request.url().as_str(),
+            format!("{WEB_URL}/api/portal/getUserSizeInfo.action?noCache=1&sessionKey=session-key")
+        );
+        assert!(!request.headers().contains_key("signature"));
+
+        let request = client
+            .client
+            .get(format!(
+                "{WEB_URL}/api/open/oauth2/getAccessTokenBySsKey.action"
+            ))
+            .build()?;
+        let request = client.before_request(request, &saved)?;
+        assert_eq!(request.headers()["appkey"], "600100422");
+
+        let request = client
+            .client
+            .get(format!(
+                "{UPLOAD_URL}/person/getUploadedPartsInfo?uploadFileId=1"
+            ))
+            .build()?;
+        let request = client.before_request(request, &saved)?;
+        assert_eq!(request.headers()["sessionkey"], "session-key");
+        assert!(request.url().query().unwrap().starts_with("params="));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_and_list() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mock.mount_list_files(
+            ROOT_FOLDER_ID,
+            json!([{
+                "id": 1,
+                "name": "a.txt",
+                "size": 12,
+                "md5": "6F5902AC237024BDD0C176CB93063DC4",
+                "lastOpTime": "2024-05-01 10:20:30",
+                "createDate": "2024-05-01 10:20:30",
+            }]),
+            json!([{
+                "id": 2,
+                "parentId": -11,
+                "name": "docs",
+                "lastOpTime": "2024-05-02 08:00:00",
+                "createDate": "2024-05-01 08:00:00",
+            }]),
+        )
+        .await;
+        let client = mock.client();
+        let list = client.list_all(&Space::Personal, ROOT_FOLDER_ID).await?;
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.find_file("a.txt").unwrap().size, 12);
+        assert_eq!(list.find_folder("docs").unwrap().id, "2");
+        assert_eq!(client.session().await?.session_key, mock::SESSION_KEY);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_renew_session() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mock.mount_list_files(ROOT_FOLDER_ID, json!([]), json!([]))
+            .await;
+        // 第一次请求返回会话失效，用登录返回的access token重新建立会话后重试
+        Mock::given(path("/api/open/file/listFiles.action"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_json(json!({"errorCode": "InvalidAccessToken"})),
+            )
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock.server)
+            .await;
+        let renew = Mock::given(method("POST"))
+            .and(path("/api/getSessionForPC.action"))
+            .and(query_param("accessToken", "login-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(mock::token_session()))
+            .with_priority(1)
+            .expect(1)
+            .mount_as_scoped(&mock.server)
+            .await;
+        let list = mock
+            .client()
+            .list_all(&Space::Personal, ROOT_FOLDER_ID)
+            .await?;
+        assert!(list.is_empty());
+        drop(renew);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_session_error() {
+        assert!(is_session_error(br#"{"errorCode":"InvalidSessionKey"}"#));
+        assert!(is_session_error(
+            br#"{"res_code":"InvalidAccessToken","res_message":"token expired"}"#
+        ));
+        assert!(is_session_error(br#"{"code":"InvalidSessionKey"}"#));
+        assert!(!is_session_error(br#"{"res_code":0,"res_message":""}"#));
+        assert!(!is_session_error(br#"{"res_code":"FileNotFound"}"#));
+        assert!(!is_session_error(b"<html></html>"));
+    }
+}
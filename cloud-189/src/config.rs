@@ -0,0 +1,48 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 配置文件，命令行参数和环境变量优先
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 默认操作的家庭云
+    pub family_id: Option<String>,
+    /// 批量签到的其他账号
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+    /// 签到结果发送到的飞书机器人
+    pub feishu_webhook: Option<String>,
+}
+
+/// 账号，没有密码时只能使用已保存的会话
+#[derive(Debug, Clone, Deserialize)]
+pub struct Account {
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+impl Config {
+    /// 默认为`~/.config/cloud-189/config.json`
+    pub fn default_path() -> Option<PathBuf> {
+        Some(
+            std::env::home_dir()?
+                .join(".config")
+                .join("cloud-189")
+                .join("config.json"),
+        )
+    }
+
+    /// 文件不存在时返回空配置
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read(path)?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("配置文件格式错误: {}", path.display()))
+    }
+}
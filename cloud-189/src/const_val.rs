@@ -1,11 +1,53 @@
-
 pub const WEB_URL: &str = "https://cloud.189.cn";
+/// 移动版网页，签到抽奖接口
+pub const M_WEB_URL: &str = "https://m.cloud.189.cn";
 pub const AUTH_URL: &str = "https://open.e.189.cn";
 pub const API_URL: &str = "https://api.cloud.189.cn";
+pub const UPLOAD_URL: &str = "https://upload.cloud.189.cn";
+
+/// 各接口的地址，默认为上面的线上地址，测试时可以指向本地服务
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseUrls {
+    pub web: String,
+    pub m_web: String,
+    pub auth: String,
+    pub api: String,
+    pub upload: String,
+}
+
+impl Default for BaseUrls {
+    fn default() -> Self {
+        Self {
+            web: WEB_URL.to_string(),
+            m_web: M_WEB_URL.to_string(),
+            auth: AUTH_URL.to_string(),
+            api: API_URL.to_string(),
+            upload: UPLOAD_URL.to_string(),
+        }
+    }
+}
+
+impl BaseUrls {
+    /// 所有接口使用同一个地址，用路径前缀区分，签名时按前缀判断接口类型
+    pub fn with_base(base: &str) -> Self {
+        let base = base.trim_end_matches('/');
+        Self {
+            web: format!("{base}/web"),
+            m_web: format!("{base}/m"),
+            auth: format!("{base}/auth"),
+            api: format!("{base}/api"),
+            upload: format!("{base}/upload"),
+        }
+    }
+}
+
+/// 个人云根目录id
+pub const ROOT_FOLDER_ID: &str = "-11";
+/// 家庭云根目录id，为空时列出根目录
+pub const FAMILY_ROOT_FOLDER_ID: &str = "";
 
 pub const APP_ID: &str = "8025431004";
 pub const ACCOUNT_TYPE: &str = "02";
 pub const CLIENT_TYPE: &str = "10020";
 pub const RETURN_URL: &str = "https://m.cloud.189.cn/zhuanti/2020/loginErrorPc/index.html";
 pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/87.0.4280.88 Safari/537.36";
-
@@ -0,0 +1,381 @@
+use crate::client::CloudClient;
+use crate::file::{CloudFile, Space};
+use crate::util;
+use anyhow::{bail, Result};
+use reqwest::{header, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::task::JoinSet;
+
+/// 下载中的临时文件后缀，完成后重命名为目标文件
+pub(crate) const PART_SUFFIX: &str = ".part";
+/// 断点续传记录的文件后缀
+pub(crate) const STATE_SUFFIX: &str = ".part.json";
+
+/// 下载参数
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// 同时下载的分段数
+    pub threads: usize,
+    /// 每个分段的字节数
+    pub chunk_size: u64,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            threads: 4,
+            chunk_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// 断点续传记录，和临时文件放在一起
+#[derive(Debug, Serialize, Deserialize)]
+struct DownloadState {
+    file_id: String,
+    size: u64,
+    md5: String,
+    chunk_size: u64,
+    /// 每个分段是否已下载完成
+    done: Vec<bool>,
+}
+
+impl DownloadState {
+    fn new(file: &CloudFile, chunk_size: u64) -> Self {
+        Self {
+            file_id: file.id.clone(),
+            size: file.size,
+            md5: file.md5.clone(),
+            chunk_size,
+            done: vec![false; chunk_ranges(file.size, chunk_size).len()],
+        }
+    }
+
+    /// 文件在网盘上被修改过时不能续传
+    fn matches(&self, file: &CloudFile, chunk_size: u64) -> bool {
+        self.file_id == file.id
+            && self.size == file.size
+            && self.md5 == file.md5
+            && self.chunk_size == chunk_size
+            && self.done.len() == chunk_ranges(file.size, chunk_size).len()
+    }
+
+    async fn load(path: &Path) -> Option<Self> {
+        let data = fs::read(path).await.ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    async fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_vec(self)?).await?;
+        Ok(())
+    }
+}
+
+/// 按分段大小拆分出闭区间`[start, end]`
+fn chunk_ranges(size: u64, chunk_size: u64) -> Vec<(u64, u64)> {
+    (0..size)
+        .step_by(chunk_size.max(1) as usize)
+        .map(|start| (start, (start + chunk_size).min(size) - 1))
+        .collect()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadUrlResponse {
+    file_download_url: String,
+}
+
+impl CloudClient {
+    /// 获取文件下载地址
+    pub async fn get_download_url(&self, space: &Space, file_id: &str) -> Result<String> {
+        let request = self
+            .client
+            .get(format!(
+                "{}{}/getFileDownloadUrl.action",
+                self.urls.api,
+                space.api_prefix()
+            ))
+            .query(&space.params())
+            .query(&[("fileId", file_id)]);
+        let res = self.send_json::<DownloadUrlResponse>(request).await?;
+        Ok(res.file_download_url.replace("&amp;", "&"))
+    }
+
+    /// 下载文件到`dest`，中断后再次调用会从已完成的分段继续，完成后校验md5
+    pub async fn download(
+        &self,
+        space: &Space,
+        file: &CloudFile,
+        dest: &Path,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        let url = self.get_download_url(space, &file.id).await?;
+        download_from_url(&self.client, &url, file, dest, options).await
+    }
+}
+
+async fn download_from_url(
+    client: &Client,
+    url: &str,
+    file: &CloudFile,
+    dest: &Path,
+    options: &DownloadOptions,
+) -> Result<()> {
+    let part_path = with_suffix(dest, PART_SUFFIX);
+    let state_path = with_suffix(dest, STATE_SUFFIX);
+    let state = match DownloadState::load(&state_path).await {
+        Some(state) if state.matches(file, options.chunk_size) && part_path.exists() => state,
+        _ => {
+            let part = fs::File::create(&part_path).await?;
+            part.set_len(file.size).await?;
+            let state = DownloadState::new(file, options.chunk_size);
+            state.save(&state_path).await?;
+            state
+        }
+    };
+
+    let ranges = chunk_ranges(file.size, options.chunk_size);
+    let pending = ranges
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !state.done[*index])
+        .map(|(index, range)| (index, *range))
+        .collect::<VecDeque<_>>();
+    let workers = options.threads.max(1).min(pending.len());
+    let pending = Arc::new(Mutex::new(pending));
+    let state = Arc::new(tokio::sync::Mutex::new(state));
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..workers {
+        let client = client.clone();
+        let url = url.to_string();
+        let size = file.size;
+        let part_path = part_path.clone();
+        let state_path = state_path.clone();
+        let pending = pending.clone();
+        let state = state.clone();
+        tasks.spawn(async move {
+            loop {
+                let Some((index, (start, end))) = pending.lock().unwrap().pop_front() else {
+                    return Ok::<(), anyhow::Error>(());
+                };
+                download_range(&client, &url, &part_path, size, start, end).await?;
+                let mut state = state.lock().await;
+                state.done[index] = true;
+                state.save(&state_path).await?;
+            }
+        });
+    }
+    let mut first_err = None;
+    while let Some(res) = tasks.join_next().await {
+        if let Err(err) = res.map_err(anyhow::Error::from).and_then(|res| res) {
+            // 出错后停止领取新的分段，已完成的分段保留用于续传
+            pending.lock().unwrap().clear();
+            first_err.get_or_insert(err);
+        }
+    }
+    if let Some(err) = first_err {
+        return Err(err);
+    }
+
+    if !file.md5.is_empty() {
+        let path = part_path.clone();
+        let md5 = tokio::task::spawn_blocking(move || util::file_md5(&path)).await??;
+        if !md5.eq_ignore_ascii_case(&file.md5) {
+            fs::remove_file(&part_path).await?;
+            fs::remove_file(&state_path).await?;
+            bail!("md5校验失败: 期望{}, 实际{}", file.md5, md5);
+        }
+    }
+    fs::rename(&part_path, dest).await?;
+    fs::remove_file(&state_path).await?;
+    Ok(())
+}
+
+/// 下载一个分段并写入临时文件的对应位置
+async fn download_range(
+    client: &Client,
+    url: &str,
+    part_path: &Path,
+    size: u64,
+    start: u64,
+    end: u64,
+) -> Result<()> {
+    let mut response = client
+        .get(url)
+        .header(header::RANGE, format!("bytes={start}-{end}"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let whole_file = start == 0 && end + 1 == size;
+    if response.status() != StatusCode::PARTIAL_CONTENT && !whole_file {
+        bail!("服务器不支持分段下载: {}", response.status());
+    }
+    let mut part = OpenOptions::new().write(true).open(part_path).await?;
+    part.seek(SeekFrom::Start(start)).await?;
+    let mut written = 0;
+    while let Some(bytes) = response.chunk().await? {
+        written += bytes.len() as u64;
+        if written > end - start + 1 {
+            bail!("分段{start}-{end}返回的数据过多");
+        }
+        part.write_all(&bytes).await?;
+    }
+    part.flush().await?;
+    if written != end - start + 1 {
+        bail!("分段{start}-{end}下载不完整: {written}字节");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    /// 按Range请求头返回数据，`fail_at`次请求后返回500
+    struct RangeResponder {
+        data: Vec<u8>,
+        requests: Arc<AtomicUsize>,
+        fail_at: usize,
+    }
+
+    impl Respond for RangeResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            if self.requests.fetch_add(1, Ordering::SeqCst) >= self.fail_at {
+                return ResponseTemplate::new(500);
+            }
+            let range = request.headers[header::RANGE].to_str().unwrap();
+            let (start, end) = range.trim_start_matches("bytes=").split_once('-').unwrap();
+            let (start, end) = (
+                start.parse::<usize>().unwrap(),
+                end.parse::<usize>().unwrap(),
+            );
+            ResponseTemplate::new(206).set_body_bytes(self.data[start..=end].to_vec())
+        }
+    }
+
+    fn cloud_file(data: &[u8]) -> CloudFile {
+        let time = NaiveDateTime::parse_from_str("2024-05-01 10:20:30", "%Y-%m-%d %H:%M:%S");
+        CloudFile {
+            id: "1".to_string(),
+            name: "data.bin".to_string(),
+            size: data.len() as u64,
+            md5: format!("{:X}", md5::compute(data)),
+            last_op_time: time.unwrap(),
+            create_date: time.unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert_eq!(chunk_ranges(0, 4), []);
+        assert_eq!(chunk_ranges(4, 4), [(0, 3)]);
+        assert_eq!(chunk_ranges(10, 4), [(0, 3), (4, 7), (8, 9)]);
+    }
+
+    #[tokio::test]
+    async fn test_download_resume() -> Result<()> {
+        let data = (0..100u8).collect::<Vec<_>>();
+        let file = cloud_file(&data);
+        let dir = std::env::temp_dir().join(format!("cloud-189-download-{}", util::timestamp()));
+        std::fs::create_dir_all(&dir)?;
+        let dest = dir.join("data.bin");
+        let options = DownloadOptions {
+            threads: 1,
+            chunk_size: 16,
+        };
+
+        // 第一次下载3个分段后失败
+        let server = MockServer::start().await;
+        let requests = Arc::new(AtomicUsize::new(0));
+        Mock::given(method("GET"))
+            .respond_with(RangeResponder {
+                data: data.clone(),
+                requests: requests.clone(),
+                fail_at: 3,
+            })
+            .mount(&server)
+            .await;
+        let client = Client::new();
+        assert!(
+            download_from_url(&client, &server.uri(), &file, &dest, &options)
+                .await
+                .is_err()
+        );
+        assert!(!dest.exists());
+
+        // 续传时只请求剩余的4个分段
+        server.reset().await;
+        let requests = Arc::new(AtomicUsize::new(0));
+        Mock::given(method("GET"))
+            .respond_with(RangeResponder {
+                data: data.clone(),
+                requests: requests.clone(),
+                fail_at: usize::MAX,
+            })
+            .mount(&server)
+            .await;
+        let options = DownloadOptions {
+            threads: 3,
+            ..options
+        };
+        download_from_url(&client, &server.uri(), &file, &dest, &options).await?;
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        assert_eq!(std::fs::read(&dest)?, data);
+        assert!(!with_suffix(&dest, ".part.json").exists());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_md5_mismatch() -> Result<()> {
+        let data = b"hello world".to_vec();
+        let mut file = cloud_file(&data);
+        file.md5 = "00000000000000000000000000000000".to_string();
+        let dir = std::env::temp_dir().join(format!("cloud-189-md5-{}", util::timestamp()));
+        std::fs::create_dir_all(&dir)?;
+        let dest = dir.join("hello.txt");
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(RangeResponder {
+                data,
+                requests: Arc::default(),
+                fail_at: usize::MAX,
+            })
+            .mount(&server)
+            .await;
+        let err = download_from_url(
+            &Client::new(),
+            &server.uri(),
+            &file,
+            &dest,
+            &DownloadOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("md5"));
+        assert!(!dest.exists());
+        assert!(!with_suffix(&dest, ".part").exists());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// 天翼云盘接口返回的错误，可以通过`anyhow::Error::downcast_ref`取出
+#[derive(Debug, thiserror::Error)]
+pub enum Cloud189Error {
+    /// 会话失效，需要刷新或重新登录
+    #[error("会话已失效: {0}")]
+    SessionExpired(String),
+    /// 登录需要输入验证码
+    #[error("登录需要验证码: {0}")]
+    CaptchaRequired(String),
+    /// 账号或密码错误
+    #[error("账号或密码错误: {0}")]
+    InvalidCredentials(String),
+    /// 空间或当日流量不足
+    #[error("空间不足: {0}")]
+    QuotaExceeded(String),
+    /// 文件、文件夹或分享不存在
+    #[error("不存在: {0}")]
+    NotFound(String),
+    /// 存在同名文件或文件夹
+    #[error("已存在: {0}")]
+    AlreadyExists(String),
+    /// 接口返回的其他错误码
+    #[error("接口返回错误: {code} {message}")]
+    Api { code: String, message: String },
+    /// 没有错误码的http错误
+    #[error("请求失败: {status}, {body}")]
+    Http { status: u16, body: String },
+    /// 响应不是预期的格式
+    #[error("无法解析响应: {error}, {body}")]
+    InvalidResponse {
+        error: serde_json::Error,
+        body: String,
+    },
+}
+
+/// 登录接口`result`：用户名或密码错误
+const LOGIN_INVALID_CREDENTIALS: i64 = -1;
+/// 登录接口`result`：校验失败，验证码错误和密码错误都会返回
+const LOGIN_CHECK_FAILED: i64 = -2;
+
+impl Cloud189Error {
+    /// 按`res_code`、`errorCode`或上传接口的`code`区分错误类型
+    pub fn from_code(code: &str, message: &str) -> Self {
+        let detail = if message.is_empty() { code } else { message }.to_string();
+        match code {
+            "InvalidSessionKey" | "InvalidAccessToken" | "UserInvalidOpenToken" => {
+                Cloud189Error::SessionExpired(code.to_string())
+            }
+            "InsufficientStorageSpace" | "UserDayFlowOverLimited" => {
+                Cloud189Error::QuotaExceeded(detail)
+            }
+            "FileNotFound" | "FolderNotFound" | "ShareNotFound" => Cloud189Error::NotFound(detail),
+            "FileAlreadyExists" | "FolderAlreadyExists" => Cloud189Error::AlreadyExists(detail),
+            _ => Cloud189Error::Api {
+                code: code.to_string(),
+                message: message.to_string(),
+            },
+        }
+    }
+
+    /// 登录接口只返回数字`result`，同一个`result`有多种原因时才看提示信息
+    pub fn from_login_result(result: i64, message: &str) -> Self {
+        let detail = message.to_string();
+        let bad_credentials = message.contains("密码") || message.contains("账号");
+        match result {
+            LOGIN_INVALID_CREDENTIALS => Cloud189Error::InvalidCredentials(detail),
+            // 提示中同时有密码错误和验证码时，重新输入验证码也无法登录
+            LOGIN_CHECK_FAILED if bad_credentials => Cloud189Error::InvalidCredentials(detail),
+            LOGIN_CHECK_FAILED if message.contains("验证码") => {
+                Cloud189Error::CaptchaRequired(detail)
+            }
+            _ => Cloud189Error::Api {
+                code: result.to_string(),
+                message: detail,
+            },
+        }
+    }
+
+    /// 刷新会话后可以重试
+    pub fn is_session_expired(&self) -> bool {
+        matches!(self, Cloud189Error::SessionExpired(_))
+    }
+}
+
+/// 各接口共有的状态字段，出错时响应中只有这些字段
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseStatus {
+    #[serde(rename = "res_code")]
+    res_code: Option<Value>,
+    #[serde(rename = "res_message")]
+    res_message: Option<String>,
+    error_code: Option<Value>,
+    error_msg: Option<String>,
+    /// 上传接口成功时为`SUCCESS`
+    code: Option<Value>,
+    msg: Option<String>,
+}
+
+impl ResponseStatus {
+    /// 状态字段表示出错时转换为对应的错误
+    pub fn check(&self) -> Result<(), Cloud189Error> {
+        let error = [
+            (&self.error_code, &self.error_msg),
+            (&self.res_code, &self.res_message),
+            (&self.code, &self.msg),
+        ]
+        .into_iter()
+        .find_map(|(code, message)| {
+            let code = match code.as_ref()? {
+                Value::String(code) => code.clone(),
+                Value::Number(code) => code.to_string(),
+                _ => return None,
+            };
+            (!matches!(code.as_str(), "0" | "SUCCESS"))
+                .then(|| (code, message.clone().unwrap_or_default()))
+        });
+        match error {
+            Some((code, message)) => Err(Cloud189Error::from_code(&code, &message)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 检查状态字段和http状态码后解析响应
+pub(crate) fn parse_response<T: DeserializeOwned>(
+    status: StatusCode,
+    body: &[u8],
+) -> Result<T, Cloud189Error> {
+    if let Ok(res) = serde_json::from_slice::<ResponseStatus>(body) {
+        res.check()?;
+    }
+    if !status.is_success() {
+        return Err(Cloud189Error::Http {
+            status: status.as_u16(),
+            body: String::from_utf8_lossy(body).to_string(),
+        });
+    }
+    serde_json::from_slice(body).map_err(|error| Cloud189Error::InvalidResponse {
+        error,
+        body: String::from_utf8_lossy(body).to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let parse = |status: u16, body: &str| {
+            parse_response::<ResponseStatus>(StatusCode::from_u16(status).unwrap(), body.as_bytes())
+        };
+        assert!(parse(200, r#"{"res_code":0,"res_message":"成功"}"#).is_ok());
+        assert!(parse(200, r#"{"code":"SUCCESS","data":{}}"#).is_ok());
+        assert!(parse(200, r#"{"fileListAO":{}}"#).is_ok());
+
+        let err = parse(400, r#"{"errorCode":"InvalidSessionKey","errorMsg":""}"#).unwrap_err();
+        assert!(err.is_session_expired());
+        let err = parse(200, r#"{"code":"InvalidSessionKey","msg":"expired"}"#).unwrap_err();
+        assert!(err.is_session_expired());
+        let err = parse(
+            200,
+            r#"{"res_code":"InsufficientStorageSpace","res_message":""}"#,
+        );
+        assert!(matches!(err, Err(Cloud189Error::QuotaExceeded(_))));
+        let err = parse(
+            200,
+            r#"{"res_code":"FileNotFound","res_message":"文件不存在"}"#,
+        );
+        assert!(matches!(err, Err(Cloud189Error::NotFound(message)) if message == "文件不存在"));
+        let err = parse(200, r#"{"res_code":1,"res_message":"错误"}"#);
+        assert!(matches!(err, Err(Cloud189Error::Api { code, .. }) if code == "1"));
+
+        assert!(matches!(
+            parse(502, "<html></html>"),
+            Err(Cloud189Error::Http { status: 502, .. })
+        ));
+        assert!(matches!(
+            parse_response::<Vec<String>>(StatusCode::OK, b"{}"),
+            Err(Cloud189Error::InvalidResponse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_login_result() {
+        assert!(matches!(
+            Cloud189Error::from_login_result(-2, "请输入验证码"),
+            Cloud189Error::CaptchaRequired(_)
+        ));
+        assert!(matches!(
+            Cloud189Error::from_login_result(-1, "用户名或密码错误"),
+            Cloud189Error::InvalidCredentials(_)
+        ));
+        assert!(matches!(
+            Cloud189Error::from_login_result(-2, "账号或密码错误，请输入验证码"),
+            Cloud189Error::InvalidCredentials(_)
+        ));
+        // 未知的result不按提示信息当作验证码错误
+        assert!(matches!(
+            Cloud189Error::from_login_result(-9, "验证码服务异常"),
+            Cloud189Error::Api { .. }
+        ));
+        assert!(matches!(
+            Cloud189Error::from_login_result(-5, "系统繁忙"),
+            Cloud189Error::Api { .. }
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::batch::{BatchTaskResult, TaskInfo, TaskType};
+use crate::client::CloudClient;
+use crate::util;
+use anyhow::Result;
+use serde::Deserialize;
+
+/// 家庭云信息
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Family {
+    #[serde(rename = "familyId", deserialize_with = "util::de_string_or_number")]
+    pub id: String,
+    #[serde(default)]
+    pub remark_name: String,
+    /// 成员数
+    #[serde(default)]
+    pub count: u32,
+    /// 1: 创建者，其他为成员
+    #[serde(default)]
+    pub user_role: i32,
+    #[serde(default)]
+    pub create_time: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FamilyListResponse {
+    #[serde(default)]
+    family_info_resp: Vec<Family>,
+}
+
+impl CloudClient {
+    /// 获取加入的家庭云
+    pub async fn get_family_list(&self) -> Result<Vec<Family>> {
+        let request = self.client.get(format!(
+            "{}/open/family/manage/getFamilyList.action",
+            self.urls.api
+        ));
+        let res = self.send_json::<FamilyListResponse>(request).await?;
+        Ok(res.family_info_resp)
+    }
+
+    /// 复制个人云的文件到家庭云的`target_folder_id`
+    pub async fn copy_to_family(
+        &self,
+        family_id: &str,
+        files: &[TaskInfo],
+        target_folder_id: &str,
+    ) -> Result<BatchTaskResult> {
+        self.copy_between_spaces(family_id, files, target_folder_id, "1")
+            .await
+    }
+
+    /// 复制家庭云的文件到个人云的`target_folder_id`
+    pub async fn copy_to_personal(
+        &self,
+        family_id: &str,
+        files: &[TaskInfo],
+        target_folder_id: &str,
+    ) -> Result<BatchTaskResult> {
+        self.copy_between_spaces(family_id, files, target_folder_id, "2")
+            .await
+    }
+
+    /// `copy_type`为1时从个人云复制到家庭云，为2时相反
+    async fn copy_between_spaces(
+        &self,
+        family_id: &str,
+        files: &[TaskInfo],
+        target_folder_id: &str,
+        copy_type: &str,
+    ) -> Result<BatchTaskResult> {
+        self.run_batch_task(
+            TaskType::Copy,
+            files,
+            &[
+                ("targetFolderId", target_folder_id.to_string()),
+                ("familyId", family_id.to_string()),
+                ("groupId", "null".to_string()),
+                ("copyType", copy_type.to_string()),
+                ("shareId", String::new()),
+            ],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_family_list() -> Result<()> {
+        let body = r#"{
+            "familyInfoResp": [{
+                "count": 2,
+                "createTime": "2024-05-01 10:20:30",
+                "expireTime": "",
+                "familyId": 300000123456,
+                "remarkName": "home",
+                "type": 1,
+                "useFlag": 1,
+                "userRole": 1
+            }]
+        }"#;
+        let families = serde_json::from_str::<FamilyListResponse>(body)?.family_info_resp;
+        assert_eq!(families.len(), 1);
+        assert_eq!(families[0].id, "300000123456");
+        assert_eq!(families[0].remark_name, "home");
+
+        let infos = serde_json::to_string(&[TaskInfo {
+            file_id: "1".to_string(),
+            file_name: "a.txt".to_string(),
+            is_folder: 0,
+        }])?;
+        assert_eq!(infos, r#"[{"fileId":"1","fileName":"a.txt","isFolder":0}]"#);
+        Ok(())
+    }
+}
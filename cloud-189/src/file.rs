@@ -0,0 +1,454 @@
+use crate::client::CloudClient;
+use crate::const_val::*;
+use crate::error::{Cloud189Error, ResponseStatus};
+use crate::util;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+
+/// 文件所在的存储空间
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Space {
+    /// 个人云
+    #[default]
+    Personal,
+    /// 家庭云，附带家庭id
+    Family(String),
+}
+
+impl Space {
+    pub fn root_folder_id(&self) -> &'static str {
+        match self {
+            Space::Personal => ROOT_FOLDER_ID,
+            Space::Family(_) => FAMILY_ROOT_FOLDER_ID,
+        }
+    }
+
+    /// API_URL下接口的路径前缀
+    pub(crate) fn api_prefix(&self) -> &'static str {
+        match self {
+            Space::Personal => "/open/file",
+            Space::Family(_) => "/open/family/file",
+        }
+    }
+
+    /// UPLOAD_URL下接口的路径前缀
+    pub(crate) fn upload_prefix(&self) -> &'static str {
+        match self {
+            Space::Personal => "/person",
+            Space::Family(_) => "/family",
+        }
+    }
+
+    /// 家庭云接口额外需要的参数
+    pub(crate) fn params(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Space::Personal => Vec::new(),
+            Space::Family(family_id) => vec![("familyId", family_id.clone())],
+        }
+    }
+}
+
+/// 文件排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrderBy {
+    /// 修改时间
+    #[default]
+    LastOpTime,
+    /// 文件名
+    FileName,
+    /// 文件大小
+    FileSize,
+}
+
+impl OrderBy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OrderBy::LastOpTime => "lastOpTime",
+            OrderBy::FileName => "filename",
+            OrderBy::FileSize => "filesize",
+        }
+    }
+}
+
+/// 文件列表分页参数
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    /// 页码，从1开始
+    pub page_num: u32,
+    pub page_size: u32,
+    pub order_by: OrderBy,
+    pub descending: bool,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            page_num: 1,
+            page_size: 100,
+            order_by: OrderBy::default(),
+            descending: true,
+        }
+    }
+}
+
+/// 文件信息
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudFile {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+    #[serde(default)]
+    pub md5: String,
+    /// 修改时间
+    #[serde(deserialize_with = "util::de_date_time")]
+    pub last_op_time: NaiveDateTime,
+    #[serde(deserialize_with = "util::de_date_time")]
+    pub create_date: NaiveDateTime,
+}
+
+/// 文件夹信息
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudFolder {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub id: String,
+    #[serde(default, deserialize_with = "util::de_string_or_number")]
+    pub parent_id: String,
+    pub name: String,
+    /// 修改时间
+    #[serde(deserialize_with = "util::de_date_time")]
+    pub last_op_time: NaiveDateTime,
+    #[serde(deserialize_with = "util::de_date_time")]
+    pub create_date: NaiveDateTime,
+}
+
+/// 一个文件夹下的文件和子文件夹
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileList {
+    /// 文件和文件夹总数，不只是当前页
+    #[serde(default)]
+    pub count: u64,
+    #[serde(rename = "fileList", default)]
+    pub files: Vec<CloudFile>,
+    #[serde(rename = "folderList", default)]
+    pub folders: Vec<CloudFolder>,
+}
+
+impl FileList {
+    /// 当前页的条目数
+    pub fn len(&self) -> usize {
+        self.files.len() + self.folders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn find_file(&self, name: &str) -> Option<&CloudFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    pub fn find_folder(&self, name: &str) -> Option<&CloudFolder> {
+        self.folders.iter().find(|folder| folder.name == name)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListFilesResponse {
+    #[serde(rename = "fileListAO")]
+    file_list: FileList,
+}
+
+/// 文件或文件夹
+#[derive(Debug, Clone)]
+pub enum Entry {
+    File(CloudFile),
+    Folder(CloudFolder),
+}
+
+impl Entry {
+    pub fn id(&self) -> &str {
+        match self {
+            Entry::File(file) => &file.id,
+            Entry::Folder(folder) => &folder.id,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Entry::File(file) => &file.name,
+            Entry::Folder(folder) => &folder.name,
+        }
+    }
+
+    pub fn is_folder(&self) -> bool {
+        matches!(self, Entry::Folder(_))
+    }
+}
+
+/// 拆分网盘路径，忽略多余的`/`
+pub fn split_path(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .collect()
+}
+
+impl CloudClient {
+    /// 分页获取文件夹下的文件和子文件夹
+    pub async fn list_files(
+        &self,
+        space: &Space,
+        folder_id: &str,
+        options: &ListOptions,
+    ) -> Result<FileList> {
+        let request = self
+            .client
+            .get(format!(
+                "{}{}/listFiles.action",
+                self.urls.api,
+                space.api_prefix()
+            ))
+            .query(&space.params())
+            .query(&[
+                ("folderId", folder_id.to_string()),
+                ("pageNum", options.page_num.to_string()),
+                ("pageSize", options.page_size.to_string()),
+                ("mediaType", "0".to_string()),
+                ("iconOption", "5".to_string()),
+                ("orderBy", options.order_by.as_str().to_string()),
+                ("descending", options.descending.to_string()),
+            ]);
+        let res = self.send_json::<ListFilesResponse>(request).await?;
+        Ok(res.file_list)
+    }
+
+    /// 获取文件夹下的全部文件和子文件夹
+    pub async fn list_all(&self, space: &Space, folder_id: &str) -> Result<FileList> {
+        let mut options = ListOptions::default();
+        let mut all = FileList::default();
+        loop {
+            let page = self.list_files(space, folder_id, &options).await?;
+            let page_len = page.len();
+            all.count = page.count;
+            all.files.extend(page.files);
+            all.folders.extend(page.folders);
+            if page_len == 0 || all.len() as u64 >= all.count {
+                return Ok(all);
+            }
+            options.page_num += 1;
+        }
+    }
+
+    /// 根据路径获取文件夹id，`/`为根目录
+    pub async fn resolve_folder(&self, space: &Space, path: &str) -> Result<String> {
+        let mut folder_id = space.root_folder_id().to_string();
+        let mut current = String::new();
+        for name in split_path(path) {
+            current = format!("{current}/{name}");
+            let list = self.list_all(space, &folder_id).await?;
+            folder_id = list
+                .find_folder(name)
+                .ok_or_else(|| Cloud189Error::NotFound(format!("文件夹{current}")))?
+                .id
+                .clone();
+        }
+        Ok(folder_id)
+    }
+
+    /// 根据路径获取文件信息
+    pub async fn resolve_file(&self, space: &Space, path: &str) -> Result<CloudFile> {
+        let mut names = split_path(path);
+        let name = names.pop().ok_or_else(|| anyhow!("文件路径为空"))?;
+        let folder_id = self.resolve_folder(space, &names.join("/")).await?;
+        self.list_all(space, &folder_id)
+            .await?
+            .find_file(name)
+            .cloned()
+            .ok_or_else(|| Cloud189Error::NotFound(format!("文件{path}")).into())
+    }
+
+    /// 根据路径获取文件或文件夹，同时返回所在文件夹的id
+    pub async fn resolve_entry(&self, space: &Space, path: &str) -> Result<(String, Entry)> {
+        let mut names = split_path(path);
+        let name = names.pop().ok_or_else(|| anyhow!("不能操作根目录"))?;
+        let folder_id = self.resolve_folder(space, &names.join("/")).await?;
+        let list = self.list_all(space, &folder_id).await?;
+        let entry = match list.find_folder(name) {
+            Some(folder) => Entry::Folder(folder.clone()),
+            None => Entry::File(
+                list.find_file(name)
+                    .cloned()
+                    .ok_or_else(|| Cloud189Error::NotFound(path.to_string()))?,
+            ),
+        };
+        Ok((folder_id, entry))
+    }
+
+    /// 在`parent_id`下创建文件夹
+    pub async fn create_folder(
+        &self,
+        space: &Space,
+        parent_id: &str,
+        name: &str,
+    ) -> Result<CloudFolder> {
+        // 家庭云的父文件夹参数名不同
+        let parent_key = match space {
+            Space::Personal => "parentFolderId",
+            Space::Family(_) => "parentId",
+        };
+        let mut form = space.params();
+        form.extend([
+            (parent_key, parent_id.to_string()),
+            ("folderName", name.to_string()),
+        ]);
+        let request = self
+            .client
+            .post(format!(
+                "{}{}/createFolder.action",
+                self.urls.api,
+                space.api_prefix()
+            ))
+            .form(&form);
+        self.send_json::<CloudFolder>(request).await
+    }
+
+    /// 按路径逐级创建文件夹，已存在的直接使用，返回最后一级的id
+    pub async fn create_folder_all(&self, space: &Space, path: &str) -> Result<String> {
+        let mut folder_id = space.root_folder_id().to_string();
+        for name in split_path(path) {
+            let list = self.list_all(space, &folder_id).await?;
+            folder_id = match list.find_folder(name) {
+                Some(folder) => folder.id.clone(),
+                None => self.create_folder(space, &folder_id, name).await?.id,
+            };
+        }
+        Ok(folder_id)
+    }
+
+    /// 重命名文件
+    pub async fn rename_file(&self, space: &Space, file_id: &str, name: &str) -> Result<()> {
+        let mut form = space.params();
+        form.extend([
+            ("fileId", file_id.to_string()),
+            ("destFileName", name.to_string()),
+        ]);
+        let request = self
+            .client
+            .post(format!(
+                "{}{}/renameFile.action",
+                self.urls.api,
+                space.api_prefix()
+            ))
+            .form(&form);
+        self.send_json::<ResponseStatus>(request).await?;
+        Ok(())
+    }
+
+    /// 重命名文件夹
+    pub async fn rename_folder(&self, space: &Space, folder_id: &str, name: &str) -> Result<()> {
+        let mut form = space.params();
+        form.extend([
+            ("folderId", folder_id.to_string()),
+            ("destFolderName", name.to_string()),
+        ]);
+        let request = self
+            .client
+            .post(format!(
+                "{}{}/renameFolder.action",
+                self.urls.api,
+                space.api_prefix()
+            ))
+            .form(&form);
+        self.send_json::<ResponseStatus>(request).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockCloud};
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_list_files() -> Result<()> {
+        let body = r#"{
+            "res_code": 0,
+            "res_message": "成功",
+            "fileListAO": {
+                "count": 2,
+                "fileListSize": 1,
+                "fileList": [{
+                    "id": 71234567890123456,
+                    "name": "a.txt",
+                    "size": 12,
+                    "md5": "6F5902AC237024BDD0C176CB93063DC4",
+                    "lastOpTime": "2024-05-01 10:20:30",
+                    "createDate": "2024-05-01 10:20:30"
+                }],
+                "folderList": [{
+                    "id": "81234567890123456",
+                    "parentId": -11,
+                    "name": "docs",
+                    "lastOpTime": "2024-05-02 08:00:00",
+                    "createDate": "2024-05-01 08:00:00"
+                }]
+            },
+            "lastRev": 20240502080000
+        }"#;
+        let list = serde_json::from_str::<ListFilesResponse>(body)?.file_list;
+        assert_eq!(list.count, 2);
+        assert_eq!(list.len(), 2);
+        let file = list.find_file("a.txt").unwrap();
+        assert_eq!(file.id, "71234567890123456");
+        assert_eq!(file.last_op_time.to_string(), "2024-05-01 10:20:30");
+        let folder = list.find_folder("docs").unwrap();
+        assert_eq!(folder.id, "81234567890123456");
+        assert_eq!(folder.parent_id, "-11");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert!(split_path("/").is_empty());
+        assert_eq!(split_path("/docs//2024/./a.txt"), ["docs", "2024", "a.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_folder_not_found() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mock.mount_list_files(
+            ROOT_FOLDER_ID,
+            json!([]),
+            json!([mock::folder_json(2, "docs")]),
+        )
+        .await;
+        mock.mount_list_files("2", json!([mock::file_json(3, "a.txt", b"a")]), json!([]))
+            .await;
+        let client = mock.client();
+
+        assert_eq!(client.resolve_folder(&Space::Personal, "/docs").await?, "2");
+        for err in [
+            client.resolve_folder(&Space::Personal, "/docs/2024").await,
+            client
+                .resolve_file(&Space::Personal, "/docs/b.txt")
+                .await
+                .map(|file| file.id),
+            client
+                .resolve_entry(&Space::Personal, "/other/a.txt")
+                .await
+                .map(|(id, _)| id),
+        ] {
+            let err = err.expect_err("路径不存在");
+            assert!(
+                matches!(err.downcast_ref(), Some(Cloud189Error::NotFound(_))),
+                "{err}"
+            );
+        }
+        Ok(())
+    }
+}
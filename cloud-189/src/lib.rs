@@ -0,0 +1,20 @@
+pub mod auth;
+pub mod batch;
+pub mod checkin;
+pub mod client;
+pub mod config;
+pub mod const_val;
+pub mod download;
+pub mod error;
+pub mod family;
+pub mod file;
+#[cfg(test)]
+mod mock;
+pub mod recycle;
+pub mod session;
+pub mod share;
+pub mod sync;
+pub mod upload;
+pub mod user;
+pub mod util;
+pub mod webdav;
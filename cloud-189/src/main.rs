@@ -1,25 +1,648 @@
-mod auth;
-mod client;
-mod const_val;
-mod util;
+use anyhow::{anyhow, bail, Result};
+use chrono::{Local, NaiveTime};
+use clap::{Parser, Subcommand, ValueEnum};
+use cloud_189::auth::QrCodeEvent;
+use cloud_189::batch::TaskInfo;
+use cloud_189::checkin;
+use cloud_189::client::CloudClient;
+use cloud_189::config::{Account, Config};
+use cloud_189::download::DownloadOptions;
+use cloud_189::error::Cloud189Error;
+use cloud_189::file::{split_path, Entry, ListOptions, Space};
+use cloud_189::session::SessionStore;
+use cloud_189::share::{ShareExpire, ShareOptions};
+use cloud_189::sync::{default_index_path, Direction, SyncIndex, SyncOptions};
+use cloud_189::upload::UploadOptions;
+use cloud_189::util;
+use cloud_189::webdav::{self, CloudDrive, CloudFs};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
 
-use anyhow::Result;
-use reqwest::Client;
-use serde_json::json;
+/// 189 cloud drive tool
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Account name, falls back to the config file
+    #[arg(short, long, global = true, env = "CLOUD189_USERNAME")]
+    username: Option<String>,
+
+    /// Account password, only needed when there is no valid saved session
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "CLOUD189_PASSWORD",
+        hide_env_values = true
+    )]
+    password: Option<String>,
+
+    /// Config file with `username`, `password`, `family_id`, `accounts` and `feishu_webhook`, defaults to `~/.config/cloud-189/config.json`
+    #[arg(long, global = true, env = "CLOUD189_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// File the login session is saved to, defaults to `~/.config/cloud-189/<username>.json`
+    #[arg(long, global = true, env = "CLOUD189_SESSION_FILE")]
+    session_file: Option<PathBuf>,
+
+    /// Operate on a family cloud instead of the personal cloud, see `families`
+    #[arg(long, global = true, env = "CLOUD189_FAMILY_ID")]
+    family: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Log in and save the session, by password unless another method is given
+    Login {
+        /// Scan a QR code with the 189 cloud app, for accounts that require SMS verification
+        #[arg(long, conflicts_with = "sso_cookie")]
+        qr: bool,
+
+        /// Value of the `SSON` cookie from a browser logged in to open.e.189.cn
+        #[arg(long, env = "CLOUD189_SSO_COOKIE", hide_env_values = true)]
+        sso_cookie: Option<String>,
+    },
+    /// Remove the saved session
+    Logout,
+    /// Show the logged in account
+    Whoami,
+    /// Show used and total storage
+    Quota,
+    /// List the family clouds the account has joined
+    Families,
+    /// List a folder
+    Ls {
+        /// Folder path in the cloud drive
+        #[arg(default_value = "/")]
+        path: String,
+
+        /// Show size and modification time
+        #[arg(short, long)]
+        long: bool,
+    },
+    /// Download a file, resuming a previous interrupted download
+    #[command(alias = "download")]
+    Get {
+        /// File path in the cloud drive, e.g. `/docs/a.pdf`
+        remote: String,
+
+        /// Local file or directory
+        #[arg(default_value = ".")]
+        local: PathBuf,
+
+        /// Number of ranges downloaded in parallel
+        #[arg(short, long, default_value_t = 4)]
+        threads: usize,
+    },
+    /// Upload a file, skipping the transfer when the drive already has the same content
+    #[command(alias = "upload")]
+    Put {
+        /// Local file
+        local: PathBuf,
+
+        /// Folder path in the cloud drive
+        #[arg(default_value = "/")]
+        remote: String,
+
+        /// Number of slices uploaded in parallel
+        #[arg(short, long, default_value_t = 3)]
+        threads: usize,
+
+        /// Overwrite a file with the same name instead of renaming
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Create a folder and any missing parents
+    Mkdir {
+        /// Folder path in the cloud drive
+        path: String,
+    },
+    /// Move files or folders into a folder, or rename a single one
+    Mv {
+        /// Paths to move
+        #[arg(required = true)]
+        sources: Vec<String>,
+
+        /// Existing target folder, or the new path when moving a single item
+        dest: String,
+    },
+    /// Delete files or folders to the recycle bin
+    Rm {
+        /// Paths to delete
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Mirror a local directory to a cloud folder, or back with `--download`
+    Sync {
+        /// Local directory
+        local: PathBuf,
+
+        /// Folder path in the cloud drive
+        remote: String,
+
+        /// Mirror the cloud folder to the local directory instead
+        #[arg(long)]
+        download: bool,
+
+        /// Delete files missing from the source, to the recycle bin on the cloud side
+        #[arg(long)]
+        delete: bool,
+
+        /// Only print what would be done
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        /// Index database caching local md5s, defaults to `.cloud-189-sync.db` in the local directory
+        #[arg(long)]
+        index: Option<PathBuf>,
+    },
+    /// Serve the drive over WebDAV, without authentication
+    Serve {
+        /// Address to listen on, keep it on localhost or a trusted network
+        #[arg(long, default_value = "127.0.0.1:8189")]
+        addr: SocketAddr,
+
+        /// Seconds a folder listing is cached before it is fetched again
+        #[arg(long, default_value_t = 30)]
+        cache_ttl: u64,
+    },
+    /// Claim the daily sign-in bonus and lottery prizes
+    Checkin {
+        /// Also check in the accounts listed under `accounts` in the config file
+        #[arg(long)]
+        all: bool,
+
+        /// Also sign in every family cloud the accounts have joined
+        #[arg(long)]
+        families: bool,
+
+        /// Keep running and check in every day at this local time, e.g. `08:30`
+        #[arg(long)]
+        daily: Option<NaiveTime>,
+
+        /// Feishu bot webhook url the summary is sent to, falls back to the config file
+        #[arg(long, env = "CLOUD189_FEISHU_WEBHOOK", hide_env_values = true)]
+        feishu_webhook: Option<String>,
+    },
+    /// Manage share links
+    Share {
+        #[command(subcommand)]
+        command: ShareCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ShareCommand {
+    /// Share a file or folder of the personal cloud
+    Create {
+        /// Path in the cloud drive
+        path: String,
+
+        /// How long the link stays valid
+        #[arg(long, value_enum, default_value_t = Expire::Week)]
+        expire: Expire,
+
+        /// Allow access without an access code
+        #[arg(long)]
+        public: bool,
+    },
+    /// List links shared by the account
+    Ls,
+    /// Cancel share links
+    Cancel {
+        /// Share ids, see `share ls`
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    /// Save everything in a share link into the personal cloud
+    Save {
+        /// Share link, e.g. `https://cloud.189.cn/t/xxxx`
+        url: String,
+
+        /// Folder path in the cloud drive
+        #[arg(default_value = "/")]
+        dest: String,
+
+        /// Access code of a private share
+        #[arg(short, long)]
+        code: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Expire {
+    Day,
+    Week,
+    Forever,
+}
+
+impl From<Expire> for ShareExpire {
+    fn from(expire: Expire) -> Self {
+        match expire {
+            Expire::Day => ShareExpire::OneDay,
+            Expire::Week => ShareExpire::SevenDays,
+            Expire::Forever => ShareExpire::Forever,
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // let cloud = CloudAuthClient::try_new()?;
-    // let res = cloud
-    //     .login_by_password("", "")
-    //     .await?;
+    // 日志输出到stderr，stdout只用于命令结果
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
+    let args = Args::parse();
+    let config = match args.config.clone().or_else(Config::default_path) {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    if let Command::Checkin {
+        all,
+        families,
+        daily,
+        feishu_webhook,
+    } = args.command
+    {
+        let mut accounts = Vec::new();
+        if let Some(username) = args.username.or(config.username) {
+            let password = args.password.or(config.password).unwrap_or_default();
+            accounts.push(Account { username, password });
+        }
+        if all {
+            accounts.extend(config.accounts);
+        }
+        if accounts.is_empty() {
+            bail!("请提供账号，或在配置文件的accounts中配置后使用--all");
+        }
+        let webhook = feishu_webhook.or(config.feishu_webhook);
+        return run_checkin(&accounts, families, daily, webhook.as_deref()).await;
+    }
+    let username = args
+        .username
+        .or(config.username)
+        .ok_or_else(|| anyhow!("请通过--username、CLOUD189_USERNAME或配置文件提供账号"))?;
+    let password = args.password.or(config.password).unwrap_or_default();
+    let space = match args.family.or(config.family_id) {
+        Some(family_id) => Space::Family(family_id),
+        None => Space::Personal,
+    };
+    let mut client = CloudClient::try_new(&username, &password)?;
+    let store = args
+        .session_file
+        .or_else(|| SessionStore::default_path(&username))
+        .map(SessionStore::new);
+    if let Some(store) = &store {
+        client = client.with_store(store.clone());
+    }
 
-    let s = "abcdefghijklmnopqrstuvwxyz";
-    for i in s.chars() {
-        for j in s.chars() {
-            println!("{}", format!("{}{}", i, j));
+    match args.command {
+        Command::Login { qr, sso_cookie } => {
+            let token = if qr {
+                client
+                    .auth_client()
+                    .login_by_qr_code(|event| match event {
+                        QrCodeEvent::Show(content) => match util::render_qr_code(&content) {
+                            Ok(code) => eprintln!("请使用天翼云盘App扫码登录:\n{code}"),
+                            Err(err) => eprintln!("二维码生成失败: {err}, 内容: {content}"),
+                        },
+                        QrCodeEvent::Scanned => eprintln!("已扫码，请在手机上确认登录"),
+                    })
+                    .await?
+            } else if let Some(cookie) = sso_cookie {
+                client.auth_client().login_by_sso_cookie(&cookie).await?
+            } else if password.is_empty() {
+                bail!("请提供密码，或使用--qr、--sso-cookie登录");
+            } else {
+                client
+                    .auth_client()
+                    .login_by_password(&username, &password)
+                    .await?
+            };
+            let login_name = token.login_name.clone();
+            client.login_with(token).await?;
+            println!("{login_name} 登录成功");
         }
+        Command::Logout => {
+            if let Some(store) = &store {
+                store.remove()?;
+            }
+        }
+        Command::Whoami => {
+            let info = client.get_user_info().await?;
+            println!("{} {}", info.user_account, info.nickname);
+        }
+        Command::Quota => {
+            let info = client.get_user_size_info().await?;
+            for (name, capacity) in [("个人云", info.personal), ("家庭云", info.family)] {
+                println!(
+                    "{name}: 已用 {} / 共 {}，剩余 {}",
+                    util::format_size(capacity.used_size),
+                    util::format_size(capacity.total_size),
+                    util::format_size(capacity.free_size)
+                );
+            }
+        }
+        Command::Families => {
+            for family in client.get_family_list().await? {
+                println!("{}\t{}", family.id, family.remark_name);
+            }
+        }
+        Command::Ls { path, long } => {
+            let folder_id = client.resolve_folder(&space, &path).await?;
+            let list = client.list_all(&space, &folder_id).await?;
+            for folder in &list.folders {
+                if long {
+                    println!("{:>10}  {}  {}/", "-", folder.last_op_time, folder.name);
+                } else {
+                    println!("{}/", folder.name);
+                }
+            }
+            for file in &list.files {
+                if long {
+                    println!(
+                        "{:>10}  {}  {}",
+                        util::format_size(file.size),
+                        file.last_op_time,
+                        file.name
+                    );
+                } else {
+                    println!("{}", file.name);
+                }
+            }
+        }
+        Command::Get {
+            remote,
+            local,
+            threads,
+        } => {
+            let file = client.resolve_file(&space, &remote).await?;
+            let dest = if local.is_dir() {
+                local.join(&file.name)
+            } else {
+                local
+            };
+            let options = DownloadOptions {
+                threads,
+                ..Default::default()
+            };
+            client.download(&space, &file, &dest, &options).await?;
+            println!("{} -> {}", remote, dest.display());
+        }
+        Command::Put {
+            local,
+            remote,
+            threads,
+            overwrite,
+        } => {
+            let folder_id = client.resolve_folder(&space, &remote).await?;
+            let options = UploadOptions { threads, overwrite };
+            let file = client.upload(&space, &local, &folder_id, &options).await?;
+            println!(
+                "{} -> {}/{}",
+                local.display(),
+                remote.trim_end_matches('/'),
+                file.file_name
+            );
+        }
+        Command::Mkdir { path } => {
+            client.create_folder_all(&space, &path).await?;
+        }
+        Command::Mv { sources, dest } => move_entries(&client, &space, &sources, &dest).await?,
+        Command::Rm { paths } => {
+            let mut items = Vec::new();
+            for path in &paths {
+                let (_, entry) = client.resolve_entry(&space, path).await?;
+                items.push(TaskInfo::from(&entry));
+            }
+            let res = client.delete_items(&space, &items).await?;
+            if !res.is_success() {
+                bail!("{}个文件删除失败", res.failed_count);
+            }
+        }
+        Command::Sync {
+            local,
+            remote,
+            download,
+            delete,
+            dry_run,
+            index,
+        } => {
+            let direction = if download {
+                Direction::Download
+            } else {
+                Direction::Upload
+            };
+            if direction == Direction::Download && !dry_run {
+                std::fs::create_dir_all(&local)?;
+            }
+            let index_path = index.unwrap_or_else(|| default_index_path(&local));
+            let index = if index_path.parent().is_some_and(|dir| dir.exists()) {
+                SyncIndex::open(&index_path)?
+            } else {
+                // 预览下载时本地目录可能还不存在
+                SyncIndex::open_in_memory()?
+            };
+            let options = SyncOptions {
+                direction,
+                delete,
+                dry_run,
+                ..Default::default()
+            };
+            let report = client
+                .sync(&space, &local, &remote, &index, &options)
+                .await?;
+            for action in &report.actions {
+                println!("{action:?}");
+            }
+            println!(
+                "{} changed, {} unchanged",
+                report.actions.len(),
+                report.unchanged
+            );
+        }
+        Command::Serve { addr, cache_ttl } => {
+            let fs = CloudFs::new(CloudDrive::new(client, space))
+                .with_cache_ttl(Duration::from_secs(cache_ttl));
+            println!("WebDAV服务已启动: http://{addr}");
+            webdav::serve(fs, addr).await?;
+        }
+        Command::Share { command } => share(&client, command).await?,
+        Command::Checkin { .. } => unreachable!("checkin runs before logging in"),
+    }
+    Ok(())
+}
+
+/// 签到所有账号并输出汇总，指定`daily`时每天定时执行
+async fn run_checkin(
+    accounts: &[Account],
+    families: bool,
+    daily: Option<NaiveTime>,
+    webhook: Option<&str>,
+) -> Result<()> {
+    loop {
+        if let Some(at) = daily {
+            tokio::time::sleep(checkin::until_next(Local::now().naive_local(), at)).await;
+        }
+        let results = checkin::run_accounts(accounts, families).await;
+        let report = checkin::format_report(&results);
+        println!("{report}");
+        if let Some(url) = webhook
+            && let Err(err) = common::feishu::send_text(url, &report).await
+        {
+            eprintln!("{err}");
+        }
+        if daily.is_none() {
+            if results.iter().any(|(_, res)| res.is_err()) {
+                bail!("部分账号签到失败");
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// 目标是已存在的文件夹时移动到其中，否则把唯一的来源移动并重命名为目标路径
+async fn move_entries(
+    client: &CloudClient,
+    space: &Space,
+    sources: &[String],
+    dest: &str,
+) -> Result<()> {
+    let mut entries = Vec::new();
+    for source in sources {
+        entries.push(client.resolve_entry(space, source).await?);
+    }
+    // 目标不存在时才按重命名处理，其他错误直接返回
+    match client.resolve_folder(space, dest).await {
+        Ok(folder_id) => {
+            let items = entries
+                .iter()
+                .map(|(_, entry)| TaskInfo::from(entry))
+                .collect::<Vec<_>>();
+            let res = client.move_items(space, &items, &folder_id).await?;
+            if !res.is_success() {
+                bail!("{}个文件移动失败", res.failed_count);
+            }
+            return Ok(());
+        }
+        Err(err) if matches!(err.downcast_ref(), Some(Cloud189Error::NotFound(_))) => {}
+        Err(err) => return Err(err),
     }
 
+    let [(parent_id, entry)] = entries.as_slice() else {
+        bail!("目标文件夹不存在: {dest}");
+    };
+    let mut names = split_path(dest);
+    let name = names.pop().ok_or_else(|| anyhow!("目标路径为空"))?;
+    let dest_parent_id = client.resolve_folder(space, &names.join("/")).await?;
+    if &dest_parent_id != parent_id {
+        let res = client
+            .move_items(space, &[TaskInfo::from(entry)], &dest_parent_id)
+            .await?;
+        if !res.is_success() {
+            bail!("移动失败: {}", entry.name());
+        }
+    }
+    if name != entry.name() {
+        match entry {
+            Entry::File(file) => client.rename_file(space, &file.id, name).await?,
+            Entry::Folder(folder) => client.rename_folder(space, &folder.id, name).await?,
+        }
+    }
     Ok(())
 }
+
+async fn share(client: &CloudClient, command: ShareCommand) -> Result<()> {
+    match command {
+        ShareCommand::Create {
+            path,
+            expire,
+            public,
+        } => {
+            let (_, entry) = client.resolve_entry(&Space::Personal, &path).await?;
+            let options = ShareOptions {
+                expire: expire.into(),
+                private: !public,
+            };
+            let link = client.create_share(entry.id(), &options).await?;
+            if link.access_code.is_empty() {
+                println!("{}", link.short_share_url);
+            } else {
+                println!("{} 访问码: {}", link.short_share_url, link.access_code);
+            }
+        }
+        ShareCommand::Ls => {
+            let mut options = ListOptions::default();
+            loop {
+                let shares = client.list_shares(&options).await?;
+                for share in &shares {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        share.share_id, share.file_name, share.access_url, share.access_code
+                    );
+                }
+                if shares.len() < options.page_size as usize {
+                    break;
+                }
+                options.page_num += 1;
+            }
+        }
+        ShareCommand::Cancel { ids } => client.cancel_shares(&ids).await?,
+        ShareCommand::Save { url, dest, code } => {
+            let share = client.open_share(&url, code.as_deref()).await?;
+            let folder_id = client.resolve_folder(&Space::Personal, &dest).await?;
+            let item = TaskInfo {
+                file_id: share.file_id.clone(),
+                file_name: share.file_name.clone(),
+                is_folder: share.is_folder.into(),
+            };
+            let res = client.save_share(&share, &[item], &folder_id).await?;
+            if !res.is_success() {
+                bail!("{}个文件转存失败", res.failed_count);
+            }
+            println!("{} -> {}", share.file_name, dest);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Args::command().debug_assert();
+
+        let args = Args::try_parse_from(["cloud-189", "mv", "/a.txt", "/b.txt", "/docs"]).unwrap();
+        let Command::Mv { sources, dest } = args.command else {
+            panic!("expected mv");
+        };
+        assert_eq!(sources, ["/a.txt", "/b.txt"]);
+        assert_eq!(dest, "/docs");
+
+        let args = Args::try_parse_from(["cloud-189", "download", "/a.txt", "--family", "1"]);
+        assert_eq!(args.unwrap().family.as_deref(), Some("1"));
+        assert!(Args::try_parse_from(["cloud-189", "rm"]).is_err());
+
+        let args = Args::try_parse_from(["cloud-189", "serve", "--addr", "0.0.0.0:8080"]).unwrap();
+        let Command::Serve { addr, cache_ttl } = args.command else {
+            panic!("expected serve");
+        };
+        assert_eq!(addr.port(), 8080);
+        assert_eq!(cache_ttl, 30);
+
+        let args = Args::try_parse_from(["cloud-189", "checkin", "--all", "--daily", "08:30"]);
+        let Command::Checkin { all, daily, .. } = args.unwrap().command else {
+            panic!("expected checkin");
+        };
+        assert!(all);
+        assert_eq!(daily, NaiveTime::from_hms_opt(8, 30, 0));
+    }
+}
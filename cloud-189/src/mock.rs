@@ -0,0 +1,271 @@
+//! 本地模拟的登录和文件接口，用于离线测试`CloudAuthClient`和`CloudClient`
+use crate::auth::CloudAuthClient;
+use crate::client::CloudClient;
+use crate::const_val::BaseUrls;
+use serde_json::{json, Value};
+use wiremock::matchers::{body_string_contains, header, method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// encryptConf返回的固定测试公钥，只用于加密账号密码
+const PUB_KEY: &str = "MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDQKA80dSA+++oEmp4hDUckgCnvjAVaz99anT1MPFnVs9vAOH7axFWebkIGM1GDwdA1s5ageN/Akqv5hEKcC74RPX491xOsGlvCVCabXUoxGqehpmkwo/L9O37tQV43X7L24SlywB5w+x8eIAJVokHGZbzbWAJSkTXf+rBDgYOaAwIDAQAB";
+const LT: &str = "mock-lt";
+const REQ_ID: &str = "mock-req-id";
+
+pub(crate) const SESSION_KEY: &str = "session-key";
+/// getAccessTokenBySsKey换取的access token，API_URL接口用它签名
+pub(crate) const API_ACCESS_TOKEN: &str = "api-token";
+
+pub(crate) struct MockCloud {
+    pub(crate) server: MockServer,
+}
+
+impl MockCloud {
+    /// 启动服务并挂载密码登录和换取access token的接口，默认不需要验证码
+    pub(crate) async fn start() -> Self {
+        let server = MockServer::start().await;
+        let urls = BaseUrls::with_base(&server.uri());
+        Mock::given(method("POST"))
+            .and(path("/auth/api/logbox/config/encryptConf.do"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "result": 0,
+                "data": {
+                    "pre": "{NRP}",
+                    "preDomain": "card.e.189.cn",
+                    "pubKey": PUB_KEY,
+                    "upSmsOn": "0",
+                },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/web/api/portal/unifyLoginForPC.action"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                "<input type='hidden' name='captchaToken' value='mock-captcha-token'>\n\
+                 <script>var lt = \"{LT}\"; var paramId = \"mock-param-id\"; \
+                 var reqId = \"{REQ_ID}\";</script>"
+            )))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/auth/api/logbox/oauth2/needcaptcha.do"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("0"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/auth/api/logbox/oauth2/loginSubmit.do"))
+            .and(header("lt", LT))
+            .and(header("reqid", REQ_ID))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "result": 0,
+                "msg": "登录成功",
+                "toUrl": format!("{}/callback", urls.web),
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/getSessionForPC.action"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(token_session()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/web/api/open/oauth2/getAccessTokenBySsKey.action"))
+            .and(query_param("sessionKey", SESSION_KEY))
+            .and(header("appkey", "600100422"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "accessToken": API_ACCESS_TOKEN,
+                "expiresIn": 2592000000u64,
+            })))
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    pub(crate) fn urls(&self) -> BaseUrls {
+        BaseUrls::with_base(&self.server.uri())
+    }
+
+    pub(crate) fn auth_client(&self) -> CloudAuthClient {
+        CloudAuthClient::try_new()
+            .unwrap()
+            .with_base_urls(self.urls())
+            .with_captcha_handler(|_| panic!("不应该需要验证码"))
+    }
+
+    /// 账号`user`，没有会话文件，首次请求时用密码登录
+    pub(crate) fn client(&self) -> CloudClient {
+        CloudClient::try_new("user", "password")
+            .unwrap()
+            .with_base_urls(self.urls())
+            .with_captcha_handler(|_| panic!("不应该需要验证码"))
+    }
+
+    /// 个人云文件夹的文件列表，要求请求带有换取的access token
+    pub(crate) async fn mount_list_files(&self, folder_id: &str, files: Value, folders: Value) {
+        let count = files.as_array().map_or(0, Vec::len) + folders.as_array().map_or(0, Vec::len);
+        Mock::given(method("GET"))
+            .and(path("/api/open/file/listFiles.action"))
+            .and(query_param("folderId", folder_id))
+            .and(header("accesstoken", API_ACCESS_TOKEN))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "res_code": 0,
+                "res_message": "成功",
+                "fileListAO": {
+                    "count": count,
+                    "fileList": files,
+                    "folderList": folders,
+                },
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// `task_type`类型的批量任务，任务id为42，依次返回`statuses`中的状态
+    pub(crate) async fn mount_batch_task(&self, task_type: &str, statuses: &[i32]) {
+        Mock::given(method("POST"))
+            .and(path("/web/api/open/batch/createBatchTask.action"))
+            .and(body_string_contains(format!("type={task_type}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "res_code": 0,
+                "res_message": "成功",
+                "taskId": 42,
+            })))
+            .mount(&self.server)
+            .await;
+        for status in statuses {
+            Mock::given(method("POST"))
+                .and(path("/web/api/open/batch/checkBatchTask.action"))
+                .and(body_string_contains("taskId=42"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "res_code": 0,
+                    "res_message": "成功",
+                    "taskId": "42",
+                    "taskStatus": status,
+                    "successedCount": 1,
+                })))
+                .up_to_n_times(1)
+                .mount(&self.server)
+                .await;
+        }
+    }
+
+    /// 个人云上传接口，参数是加密的，只按路径匹配
+    ///
+    /// `file_data_exists`时秒传，否则`uploaded_parts`中的分片已上传，
+    /// 其余分片PUT到`/oss/part{n}`
+    pub(crate) async fn mount_upload(&self, file_data_exists: bool, uploaded_parts: &str) {
+        let upload = |name: &str| {
+            Mock::given(method("GET"))
+                .and(path(format!("/upload/person/{name}")))
+                .and(header("sessionkey", SESSION_KEY))
+        };
+        upload("initMultiUpload")
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": "SUCCESS",
+                "data": {
+                    "uploadFileId": "upload-1",
+                    "fileDataExists": u8::from(file_data_exists),
+                },
+            })))
+            .mount(&self.server)
+            .await;
+        upload("getUploadedPartsInfo")
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": "SUCCESS",
+                "data": {
+                    "uploadFileId": "upload-1",
+                    "uploadedPartList": uploaded_parts,
+                },
+            })))
+            .mount(&self.server)
+            .await;
+        // 分片序号也在加密的参数中，每次都返回所有分片的地址
+        let urls = (1..=3)
+            .map(|part| {
+                (
+                    format!("partNumber_{part}"),
+                    json!({
+                        "requestURL": format!("{}/oss/part{part}", self.server.uri()),
+                        "requestHeader": "x-amz-date=20240501T000000Z",
+                    }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        upload("getMultiUploadUrls")
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": "SUCCESS",
+                "uploadUrls": urls,
+            })))
+            .mount(&self.server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path_regex("^/oss/part[0-9]+$"))
+            .and(header("x-amz-date", "20240501T000000Z"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&self.server)
+            .await;
+        upload("commitMultiUploadFile")
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": "SUCCESS",
+                "file": {
+                    "userFileId": 300,
+                    "fileName": "uploaded",
+                    "fileSize": 0,
+                    "fileMd5": "",
+                },
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// 收到的请求中路径为`path`的请求数
+    pub(crate) async fn request_count(&self, path: &str) -> usize {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter(|request| request.url.path() == path)
+            .count()
+    }
+}
+
+/// listFiles中的文件，大小和md5按`content`计算
+pub(crate) fn file_json(id: u64, name: &str, content: &[u8]) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "size": content.len(),
+        "md5": format!("{:X}", md5::compute(content)),
+        "lastOpTime": "2024-05-01 10:20:30",
+        "createDate": "2024-05-01 10:20:30",
+    })
+}
+
+/// listFiles中的文件夹
+pub(crate) fn folder_json(id: u64, name: &str) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "lastOpTime": "2024-05-01 10:20:30",
+        "createDate": "2024-05-01 10:20:30",
+    })
+}
+
+/// getSessionForPC返回的会话
+pub(crate) fn token_session() -> Value {
+    json!({
+        "res_code": 0,
+        "res_message": "",
+        "accessToken": "login-token",
+        "familySessionKey": "family-key",
+        "familySessionSecret": "family-secret-0123",
+        "refreshToken": "refresh",
+        "loginName": "user",
+        "sessionKey": SESSION_KEY,
+        "sessionSecret": "0123456789abcdef0123",
+        "getFileDiffSpan": 0,
+        "getUserInfoSpan": 0,
+        "isSaveName": "false",
+        "keepAlive": 1800,
+    })
+}
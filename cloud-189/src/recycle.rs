@@ -0,0 +1,79 @@
+use crate::batch::{BatchTaskResult, TaskInfo, TaskType};
+use crate::client::CloudClient;
+use crate::file::{FileList, ListOptions};
+use anyhow::Result;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecycleBinResponse {
+    #[serde(default)]
+    record_count: u64,
+    #[serde(flatten)]
+    list: FileList,
+}
+
+impl CloudClient {
+    /// 分页获取回收站中的文件和文件夹
+    pub async fn list_recycle_bin(&self, options: &ListOptions) -> Result<FileList> {
+        let request = self
+            .client
+            .get(format!(
+                "{}/api/open/file/listRecycleBinFiles.action",
+                self.urls.web
+            ))
+            .query(&[
+                ("pageNum", options.page_num.to_string()),
+                ("pageSize", options.page_size.to_string()),
+                ("iconOption", "1".to_string()),
+                ("family", "false".to_string()),
+            ]);
+        let res = self.send_json::<RecycleBinResponse>(request).await?;
+        Ok(FileList {
+            count: res.record_count,
+            ..res.list
+        })
+    }
+
+    /// 从回收站还原到原位置
+    pub async fn restore_items(&self, items: &[TaskInfo]) -> Result<BatchTaskResult> {
+        self.run_batch_task(TaskType::Restore, items, &[]).await
+    }
+
+    /// 从回收站彻底删除
+    pub async fn delete_forever(&self, items: &[TaskInfo]) -> Result<BatchTaskResult> {
+        self.run_batch_task(TaskType::ClearRecycle, items, &[])
+            .await
+    }
+
+    /// 清空回收站
+    pub async fn empty_recycle_bin(&self) -> Result<BatchTaskResult> {
+        self.run_batch_task(TaskType::EmptyRecycle, &[], &[]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_recycle_bin() -> Result<()> {
+        let body = r#"{
+            "res_code": 0,
+            "recordCount": 1,
+            "fileList": [{
+                "id": 71234567890123456,
+                "name": "a.txt",
+                "size": 12,
+                "md5": "6F5902AC237024BDD0C176CB93063DC4",
+                "lastOpTime": "2024-05-01 10:20:30",
+                "createDate": "2024-05-01 10:20:30"
+            }],
+            "folderList": []
+        }"#;
+        let res = serde_json::from_str::<RecycleBinResponse>(body)?;
+        assert_eq!(res.record_count, 1);
+        assert_eq!(res.list.files[0].name, "a.txt");
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+use crate::auth::TokenSession;
+use crate::client::CloudClient;
+use crate::error::{self, Cloud189Error};
+use anyhow::{Context, Result};
+use common::time::timestamp_s;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// 接口没有返回有效期时，会话的默认有效期，单位秒
+const SESSION_DEFAULT_LIFETIME: u64 = 24 * 60 * 60;
+/// 距离过期不到该时间时先刷新再使用，单位秒
+const SESSION_REFRESH_MARGIN: u64 = 10 * 60;
+
+/// 保存到本地的会话
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    /// 会话所属账号
+    pub username: String,
+    pub token: TokenSession,
+    /// 调用API_URL接口用的access token，由session key换取，和登录返回的不同
+    #[serde(default)]
+    pub api_access_token: String,
+    /// 会话建立时间，单位秒
+    pub created_at: u64,
+    /// 会话过期时间，单位秒，旧版本保存的会话没有该字段，会先刷新一次
+    #[serde(default)]
+    pub expires_at: u64,
+}
+
+impl SavedSession {
+    /// 有效期未知，按默认有效期计算过期时间
+    pub fn new(username: &str, token: TokenSession) -> Self {
+        let now = timestamp_s();
+        Self {
+            username: username.to_string(),
+            token,
+            api_access_token: String::new(),
+            created_at: now,
+            expires_at: now + SESSION_DEFAULT_LIFETIME,
+        }
+    }
+
+    /// 按接口返回的有效期设置过期时间，`expires_in`为0时不变
+    pub fn with_expires_in(mut self, expires_in: u64) -> Self {
+        if expires_in > 0 {
+            self.expires_at = self.created_at + expires_in;
+        }
+        self
+    }
+
+    fn need_refresh(&self) -> bool {
+        timestamp_s() + SESSION_REFRESH_MARGIN >= self.expires_at
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessTokenResponse {
+    access_token: String,
+}
+
+/// 会话文件
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    path: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// 默认保存在`~/.config/cloud-189/<账号>.json`
+    pub fn default_path(username: &str) -> Option<PathBuf> {
+        let home = std::env::home_dir()?;
+        Some(
+            home.join(".config")
+                .join("cloud-189")
+                .join(format!("{username}.json")),
+        )
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn load(&self) -> Option<SavedSession> {
+        let data = fs::read(&self.path).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// 先写入同目录下的临时文件再替换，写入中断时不会留下不完整的会话文件
+    pub fn save(&self, session: &SavedSession) -> Result<()> {
+        let data = serde_json::to_vec_pretty(session)?;
+        let parent = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)?;
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = parent.join(format!(".{file_name}.{:016x}.tmp", rand::random::<u64>()));
+        let res = write_private(&temp, &data).and_then(|_| fs::rename(&temp, &self.path));
+        if res.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        Ok(res?)
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// 新建只允许本人读写的文件，会话可以直接访问网盘
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+impl CloudClient {
+    /// 获取当前会话，依次尝试内存、会话文件和账号密码登录，会话快过期时先刷新
+    pub async fn session(&self) -> Result<TokenSession> {
+        Ok(self.current_session().await?.token)
+    }
+
+    /// 获取用于签名的会话，缺少API_URL的access token时先换取
+    pub(crate) async fn current_session(&self) -> Result<SavedSession> {
+        let mut current = self.session.lock().await;
+        if current.is_none() {
+            *current = self
+                .store
+                .as_ref()
+                .and_then(SessionStore::load)
+                .filter(|saved| saved.username == self.username);
+        }
+        let saved = match current.take() {
+            Some(saved) if !saved.need_refresh() => saved,
+            Some(saved) => self.save_session(self.refresh_session(saved).await?)?,
+            None => self.save_session(self.login_by_password().await?)?,
+        };
+        let saved = if saved.api_access_token.is_empty() {
+            let api_access_token = self.get_access_token_by_session_key(&saved).await?;
+            self.save_session(SavedSession {
+                api_access_token,
+                ..saved
+            })?
+        } else {
+            saved
+        };
+        *current = Some(saved.clone());
+        Ok(saved)
+    }
+
+    /// 用session key换取API_URL接口的access token
+    async fn get_access_token_by_session_key(&self, saved: &SavedSession) -> Result<String> {
+        let request = self
+            .client
+            .get(format!(
+                "{}/api/open/oauth2/getAccessTokenBySsKey.action",
+                self.urls.web
+            ))
+            .query(&[("sessionKey", &saved.token.session_key)]);
+        let (status, body) = self.execute(request, saved).await?;
+        let res = error::parse_response::<AccessTokenResponse>(status, &body)?;
+        Ok(res.access_token)
+    }
+
+    /// 使用其他方式登录得到的会话，替换当前会话并保存
+    pub async fn login_with(&self, token: TokenSession) -> Result<()> {
+        let saved = SavedSession::new(&self.username, token);
+        *self.session.lock().await = Some(saved.clone());
+        self.save_session(saved)?;
+        Ok(())
+    }
+
+    /// 请求返回会话失效时重新建立会话，`expired_key`为失效的session key
+    pub(crate) async fn renew_session(&self, expired_key: &str) -> Result<()> {
+        let mut current = self.session.lock().await;
+        let saved = match current.take() {
+            // 其他请求已经重新建立过会话
+            Some(saved) if saved.token.session_key != expired_key => saved,
+            Some(saved) => self.save_session(self.refresh_session(saved).await?)?,
+            None => self.save_session(self.login_by_password().await?)?,
+        };
+        *current = Some(saved);
+        Ok(())
+    }
+
+    /// 依次尝试access token、refresh token和账号密码
+    async fn refresh_session(&self, saved: SavedSession) -> Result<SavedSession> {
+        let old = saved.token;
+        if let Ok(token) = self
+            .auth_client
+            .login_by_access_token(&old.access_token)
+            .await
+        {
+            return Ok(SavedSession::new(
+                &self.username,
+                keep_refresh_token(token, &old),
+            ));
+        }
+        if !old.refresh_token.is_empty()
+            && let Ok(access) = self.auth_client.refresh_token(&old.refresh_token).await
+            && let Ok(mut token) = self
+                .auth_client
+                .login_by_access_token(&access.access_token)
+                .await
+        {
+            if !access.refresh_token.is_empty() {
+                token.refresh_token = access.refresh_token;
+            }
+            return Ok(
+                SavedSession::new(&self.username, keep_refresh_token(token, &old))
+                    .with_expires_in(access.expires_in),
+            );
+        }
+        self.login_by_password().await
+    }
+
+    async fn login_by_password(&self) -> Result<SavedSession> {
+        if self.password.is_empty() {
+            return Err(Cloud189Error::SessionExpired("请重新登录".to_string()).into());
+        }
+        let token = self
+            .auth_client
+            .login_by_password(&self.username, &self.password)
+            .await?;
+        Ok(SavedSession::new(&self.username, token))
+    }
+
+    /// 有会话文件时保存，返回保存的会话
+    fn save_session(&self, saved: SavedSession) -> Result<SavedSession> {
+        if let Some(store) = &self.store {
+            store
+                .save(&saved)
+                .with_context(|| format!("保存会话到{}失败", store.path().display()))?;
+        }
+        Ok(saved)
+    }
+}
+
+/// 刷新接口可能不返回refresh token，沿用旧的
+fn keep_refresh_token(mut token: TokenSession, old: &TokenSession) -> TokenSession {
+    if token.refresh_token.is_empty() {
+        token.refresh_token = old.refresh_token.clone();
+    }
+    token
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    fn token(session_key: &str) -> TokenSession {
+        let mut value = mock::token_session();
+        value["sessionKey"] = session_key.into();
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_session_store() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!("cloud-189-session-{}", crate::util::timestamp()))
+            .join("user.json");
+        let store = SessionStore::new(&path);
+        assert!(store.load().is_none());
+
+        let saved = SavedSession::new("user", token("key"));
+        assert!(!saved.need_refresh());
+        store.save(&saved)?;
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.username, "user");
+        assert_eq!(loaded.token.session_key, "key");
+        assert_eq!(loaded.expires_at, saved.expires_at);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // 覆盖已有的会话文件，不留下临时文件
+        store.save(&saved)?;
+        assert_eq!(fs::read_dir(path.parent().unwrap())?.count(), 1);
+
+        // 有效期不足刷新余量时提前刷新
+        assert!(saved.clone().with_expires_in(5 * 60).need_refresh());
+        let saved = saved.with_expires_in(30 * 24 * 60 * 60);
+        assert_eq!(saved.expires_at, saved.created_at + 30 * 24 * 60 * 60);
+        assert!(!saved.need_refresh());
+        assert!(!saved.clone().with_expires_in(0).need_refresh());
+
+        // 旧版本的会话文件没有过期时间
+        let mut value = serde_json::to_value(&saved)?;
+        value.as_object_mut().unwrap().remove("expires_at");
+        assert!(serde_json::from_value::<SavedSession>(value)?.need_refresh());
+
+        store.remove()?;
+        assert!(store.load().is_none());
+        fs::remove_dir_all(path.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_refresh_token() {
+        let mut token = token("new");
+        token.refresh_token.clear();
+        let token = keep_refresh_token(token, &self::token("old"));
+        assert_eq!(token.refresh_token, "refresh");
+        assert_eq!(token.session_key, "new");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::batch::{BatchTaskResult, TaskInfo, TaskType};
+use crate::client::CloudClient;
+use crate::error::ResponseStatus;
+use crate::file::{FileList, ListOptions};
+use crate::util;
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// 分享有效期
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShareExpire {
+    OneDay,
+    #[default]
+    SevenDays,
+    Forever,
+}
+
+impl ShareExpire {
+    /// 接口用天数表示有效期，2099为永久
+    fn days(&self) -> u32 {
+        match self {
+            ShareExpire::OneDay => 1,
+            ShareExpire::SevenDays => 7,
+            ShareExpire::Forever => 2099,
+        }
+    }
+}
+
+/// 创建分享的参数
+#[derive(Debug, Clone)]
+pub struct ShareOptions {
+    pub expire: ShareExpire,
+    /// 私密分享，需要访问码才能查看
+    pub private: bool,
+}
+
+impl Default for ShareOptions {
+    fn default() -> Self {
+        Self {
+            expire: ShareExpire::default(),
+            private: true,
+        }
+    }
+}
+
+/// 新创建的分享链接
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareLink {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub share_id: String,
+    #[serde(alias = "url", alias = "accessUrl")]
+    pub short_share_url: String,
+    /// 公开分享为空
+    #[serde(default)]
+    pub access_code: String,
+}
+
+/// 自己创建的分享
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MyShare {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub share_id: String,
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub file_id: String,
+    pub file_name: String,
+    #[serde(default)]
+    pub is_folder: bool,
+    #[serde(rename = "accessURL", default)]
+    pub access_url: String,
+    #[serde(default)]
+    pub access_code: String,
+    #[serde(default)]
+    pub share_time: String,
+    /// 剩余有效天数
+    #[serde(default)]
+    pub expire_time: i64,
+}
+
+/// 打开的公开分享
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedResource {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub share_id: String,
+    /// 分享的根文件或文件夹
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    pub file_id: String,
+    pub file_name: String,
+    #[serde(default)]
+    pub is_folder: bool,
+    /// 1: 私密分享
+    #[serde(default)]
+    pub share_mode: i32,
+    #[serde(skip)]
+    pub access_code: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateShareResponse {
+    share_link_list: Vec<ShareLink>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListSharesResponse {
+    #[serde(default)]
+    data: Vec<MyShare>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckAccessCodeResponse {
+    #[serde(deserialize_with = "util::de_string_or_number")]
+    share_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListShareDirResponse {
+    #[serde(rename = "fileListAO")]
+    file_list: FileList,
+}
+
+/// 从分享链接中取出分享码，支持`/t/<code>`和`?code=<code>`两种形式
+pub fn parse_share_code(url: &str) -> Result<String> {
+    let re = Regex::new(r"(?:/t/|[?&]code=)([0-9A-Za-z]+)")?;
+    re.captures(url)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().to_string())
+        .ok_or_else(|| anyhow!("无法识别的分享链接: {url}"))
+}
+
+impl CloudClient {
+    /// 分享个人云中的文件或文件夹
+    pub async fn create_share(&self, file_id: &str, options: &ShareOptions) -> Result<ShareLink> {
+        // 3: 私密分享，2: 公开分享
+        let share_type = if options.private { "3" } else { "2" };
+        let request = self
+            .client
+            .get(format!(
+                "{}/api/open/share/createShareLink.action",
+                self.urls.web
+            ))
+            .query(&[
+                ("fileId", file_id.to_string()),
+                ("expireTime", options.expire.days().to_string()),
+                ("shareType", share_type.to_string()),
+            ]);
+        let res = self.send_json::<CreateShareResponse>(request).await?;
+        res.share_link_list
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("创建分享未返回链接"))
+    }
+
+    /// 分页获取自己创建的分享
+    pub async fn list_shares(&self, options: &ListOptions) -> Result<Vec<MyShare>> {
+        let request = self
+            .client
+            .get(format!("{}/api/portal/listShares.action", self.urls.web))
+            .query(&[
+                ("pageNum", options.page_num.to_string()),
+                ("pageSize", options.page_size.to_string()),
+                ("shareType", "1".to_string()),
+            ]);
+        let res = self.send_json::<ListSharesResponse>(request).await?;
+        Ok(res.data)
+    }
+
+    /// 取消分享
+    pub async fn cancel_shares(&self, share_ids: &[String]) -> Result<()> {
+        let request = self
+            .client
+            .post(format!("{}/api/portal/cancelShare.action", self.urls.web))
+            .form(&[
+                ("shareIdList", share_ids.join(",")),
+                ("cancelType", "1".to_string()),
+            ]);
+        self.send_json::<ResponseStatus>(request).await?;
+        Ok(())
+    }
+
+    /// 打开分享链接，私密分享需要访问码
+    pub async fn open_share(&self, url: &str, access_code: Option<&str>) -> Result<SharedResource> {
+        let share_code = parse_share_code(url)?;
+        let request = self
+            .client
+            .get(format!(
+                "{}/api/open/share/getShareInfoByCodeV2.action",
+                self.urls.web
+            ))
+            .query(&[("shareCode", &share_code)]);
+        let mut share = self.send_json::<SharedResource>(request).await?;
+        if share.share_mode == 1 {
+            let Some(access_code) = access_code else {
+                bail!("私密分享需要访问码");
+            };
+            let request = self
+                .client
+                .get(format!(
+                    "{}/api/open/share/checkAccessCode.action",
+                    self.urls.web
+                ))
+                .query(&[
+                    ("shareCode", share_code.as_str()),
+                    ("accessCode", access_code),
+                ]);
+            share.share_id = self
+                .send_json::<CheckAccessCodeResponse>(request)
+                .await?
+                .share_id;
+            share.access_code = access_code.to_string();
+        }
+        Ok(share)
+    }
+
+    /// 分页获取分享中的文件，`folder_id`为空时列出分享的根文件夹
+    pub async fn list_share_files(
+        &self,
+        share: &SharedResource,
+        folder_id: Option<&str>,
+        options: &ListOptions,
+    ) -> Result<FileList> {
+        let request = self
+            .client
+            .get(format!(
+                "{}/api/open/share/listShareDir.action",
+                self.urls.web
+            ))
+            .query(&[
+                ("pageNum", options.page_num.to_string()),
+                ("pageSize", options.page_size.to_string()),
+                ("fileId", folder_id.unwrap_or(&share.file_id).to_string()),
+                (
+                    "shareDirFileId",
+                    folder_id.unwrap_or(&share.file_id).to_string(),
+                ),
+                ("isFolder", share.is_folder.to_string()),
+                ("shareId", share.share_id.clone()),
+                ("shareMode", share.share_mode.to_string()),
+                ("iconOption", "5".to_string()),
+                ("orderBy", "lastOpTime".to_string()),
+                ("descending", "true".to_string()),
+                ("accessCode", share.access_code.clone()),
+            ]);
+        let res = self.send_json::<ListShareDirResponse>(request).await?;
+        Ok(res.file_list)
+    }
+
+    /// 转存分享中的文件到自己的`target_folder_id`
+    pub async fn save_share(
+        &self,
+        share: &SharedResource,
+        items: &[TaskInfo],
+        target_folder_id: &str,
+    ) -> Result<BatchTaskResult> {
+        self.run_batch_task(
+            TaskType::ShareSave,
+            items,
+            &[
+                ("targetFolderId", target_folder_id.to_string()),
+                ("shareId", share.share_id.clone()),
+            ],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_share_code() -> Result<()> {
+        assert_eq!(parse_share_code("https://cloud.189.cn/t/AbCd12")?, "AbCd12");
+        assert_eq!(
+            parse_share_code("https://cloud.189.cn/web/share?code=AbCd12（访问码：x1y2）")?,
+            "AbCd12"
+        );
+        assert!(parse_share_code("https://cloud.189.cn/web/main/").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_share_info() -> Result<()> {
+        let body = r#"{
+            "res_code": 0,
+            "fileId": "71234567890123456",
+            "fileName": "docs",
+            "isFolder": true,
+            "shareId": 12345678,
+            "shareMode": 1,
+            "needAccessCode": 1
+        }"#;
+        let share = serde_json::from_str::<SharedResource>(body)?;
+        assert_eq!(share.share_id, "12345678");
+        assert!(share.is_folder);
+        assert!(share.access_code.is_empty());
+
+        let body = r#"{"shareLinkList":[{"shareId":1,"url":"https://cloud.189.cn/t/AbCd12","accessCode":"x1y2"}]}"#;
+        let link = serde_json::from_str::<CreateShareResponse>(body)?.share_link_list;
+        assert_eq!(link[0].short_share_url, "https://cloud.189.cn/t/AbCd12");
+        assert_eq!(link[0].access_code, "x1y2");
+        Ok(())
+    }
+}
@@ -0,0 +1,660 @@
+use crate::batch::TaskInfo;
+use crate::client::CloudClient;
+use crate::download::{DownloadOptions, PART_SUFFIX, STATE_SUFFIX};
+use crate::file::{CloudFile, Space};
+use crate::upload::UploadOptions;
+use crate::util;
+use anyhow::{bail, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// 默认的索引文件名，放在本地目录下，同步时忽略
+pub const INDEX_FILE_NAME: &str = ".cloud-189-sync.db";
+
+/// 同步方向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// 本地目录同步到网盘
+    #[default]
+    Upload,
+    /// 网盘同步到本地目录
+    Download,
+}
+
+/// 同步参数
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub direction: Direction,
+    /// 删除目标中多出的文件和文件夹，网盘上删除到回收站
+    pub delete: bool,
+    /// 只计算需要执行的操作
+    pub dry_run: bool,
+    pub upload: UploadOptions,
+    pub download: DownloadOptions,
+}
+
+/// 同步操作，路径相对于同步的根目录，用`/`分隔
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    CreateRemoteFolder(String),
+    CreateLocalFolder(String),
+    Upload(String),
+    Download(String),
+    DeleteRemote(String),
+    DeleteRemoteFolder(String),
+    DeleteLocal(String),
+    DeleteLocalFolder(String),
+}
+
+/// 同步结果
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// 已执行的操作，`dry_run`时为计划执行的操作
+    pub actions: Vec<Action>,
+    /// 内容相同而跳过的文件数
+    pub unchanged: usize,
+}
+
+/// 文件大小和md5
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDigest {
+    pub size: u64,
+    /// 大写十六进制
+    pub md5: String,
+}
+
+/// 同步一侧的文件和文件夹
+#[derive(Debug, Default)]
+pub struct Tree {
+    pub files: BTreeMap<String, FileDigest>,
+    /// 不包含根目录
+    pub folders: BTreeSet<String>,
+}
+
+/// 本地文件的md5索引，大小和修改时间没有变化时直接使用记录的md5
+pub struct SyncIndex {
+    conn: Connection,
+}
+
+impl SyncIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// 不保存的索引
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS files (
+                path TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                mtime INTEGER NOT NULL,
+                md5 TEXT NOT NULL
+            )",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// 获取文件md5，文件变化时重新计算并更新索引
+    pub fn md5(&self, root: &Path, rel: &str) -> Result<FileDigest> {
+        let path = root.join(rel);
+        let meta = fs::metadata(&path)?;
+        let size = meta.len();
+        let mtime = meta.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as i64;
+        let cached = self
+            .conn
+            .query_row(
+                "SELECT md5 FROM files WHERE path = ?1 AND size = ?2 AND mtime = ?3",
+                params![rel, size as i64, mtime],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        let md5 = match cached {
+            Some(md5) => md5,
+            None => {
+                let md5 = util::file_md5(&path)?;
+                self.conn.execute(
+                    "INSERT OR REPLACE INTO files (path, size, mtime, md5) VALUES (?1, ?2, ?3, ?4)",
+                    params![rel, size as i64, mtime, md5],
+                )?;
+                md5
+            }
+        };
+        Ok(FileDigest { size, md5 })
+    }
+
+    /// 删除已不存在的文件的记录
+    pub fn retain(&self, files: &BTreeMap<String, FileDigest>) -> Result<()> {
+        let mut stmt = self.conn.prepare("SELECT path FROM files")?;
+        let paths = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for path in paths.iter().filter(|path| !files.contains_key(*path)) {
+            self.conn
+                .execute("DELETE FROM files WHERE path = ?1", params![path])?;
+        }
+        Ok(())
+    }
+}
+
+fn join_rel(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+fn parent_rel(rel: &str) -> &str {
+    rel.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// 遍历本地目录，跳过索引文件、sqlite的日志文件和未完成下载的临时文件
+pub fn local_tree(root: &Path, index: &SyncIndex) -> Result<Tree> {
+    let mut tree = Tree::default();
+    let mut pending = vec![String::new()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if dir.is_empty() && name.starts_with(INDEX_FILE_NAME) {
+                continue;
+            }
+            let rel = join_rel(&dir, &name);
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                tree.folders.insert(rel.clone());
+                pending.push(rel);
+            } else if file_type.is_file()
+                && !name.ends_with(PART_SUFFIX)
+                && !name.ends_with(STATE_SUFFIX)
+            {
+                tree.files.insert(rel.clone(), index.md5(root, &rel)?);
+            }
+        }
+    }
+    Ok(tree)
+}
+
+/// 根据两侧的差异计算同步操作，被删除的文件夹中的文件不再单独删除
+pub fn plan(
+    local: &Tree,
+    remote: &Tree,
+    direction: Direction,
+    delete: bool,
+) -> (Vec<Action>, usize) {
+    let (source, target) = match direction {
+        Direction::Upload => (local, remote),
+        Direction::Download => (remote, local),
+    };
+    let mut actions = Vec::new();
+    let mut unchanged = 0;
+    // 文件所在的文件夹会在传输时创建，这里保证空文件夹也同步
+    for rel in source.folders.difference(&target.folders) {
+        actions.push(match direction {
+            Direction::Upload => Action::CreateRemoteFolder(rel.clone()),
+            Direction::Download => Action::CreateLocalFolder(rel.clone()),
+        });
+    }
+    for (rel, digest) in &source.files {
+        match target.files.get(rel) {
+            Some(existing)
+                if existing.size == digest.size
+                    && existing.md5.eq_ignore_ascii_case(&digest.md5) =>
+            {
+                unchanged += 1;
+            }
+            _ => actions.push(match direction {
+                Direction::Upload => Action::Upload(rel.clone()),
+                Direction::Download => Action::Download(rel.clone()),
+            }),
+        }
+    }
+    if delete {
+        let kept = |rel: &str| {
+            let parent = parent_rel(rel);
+            parent.is_empty() || source.folders.contains(parent)
+        };
+        for rel in &target.folders {
+            if !source.folders.contains(rel) && kept(rel) {
+                actions.push(match direction {
+                    Direction::Upload => Action::DeleteRemoteFolder(rel.clone()),
+                    Direction::Download => Action::DeleteLocalFolder(rel.clone()),
+                });
+            }
+        }
+        for rel in target.files.keys() {
+            if !source.files.contains_key(rel) && kept(rel) {
+                actions.push(match direction {
+                    Direction::Upload => Action::DeleteRemote(rel.clone()),
+                    Direction::Download => Action::DeleteLocal(rel.clone()),
+                });
+            }
+        }
+    }
+    (actions, unchanged)
+}
+
+/// 网盘上的文件和文件夹id
+#[derive(Default)]
+struct RemoteIds {
+    files: HashMap<String, CloudFile>,
+    /// 包含根目录，键为空字符串
+    folders: HashMap<String, String>,
+}
+
+impl CloudClient {
+    /// 同步本地目录和网盘文件夹，`index`记录本地文件的md5
+    pub async fn sync(
+        &self,
+        space: &Space,
+        local: &Path,
+        remote: &str,
+        index: &SyncIndex,
+        options: &SyncOptions,
+    ) -> Result<SyncReport> {
+        let local_tree = if local.is_dir() {
+            local_tree(local, index)?
+        } else if options.direction == Direction::Download {
+            Tree::default()
+        } else {
+            bail!("本地目录不存在: {}", local.display());
+        };
+        index.retain(&local_tree.files)?;
+        // 预览上传时不创建网盘文件夹，不存在时当作空文件夹
+        let root_id = match (options.direction, options.dry_run) {
+            (Direction::Upload, false) => Some(self.create_folder_all(space, remote).await?),
+            (Direction::Upload, true) => self.resolve_folder(space, remote).await.ok(),
+            (Direction::Download, _) => Some(self.resolve_folder(space, remote).await?),
+        };
+        let (remote_tree, mut ids) = match &root_id {
+            Some(root_id) => self.remote_tree(space, root_id).await?,
+            None => Default::default(),
+        };
+        let (actions, unchanged) =
+            plan(&local_tree, &remote_tree, options.direction, options.delete);
+        let report = SyncReport { actions, unchanged };
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        let mut remote_deletes = Vec::new();
+        for action in &report.actions {
+            match action {
+                Action::CreateRemoteFolder(rel) => {
+                    self.ensure_folder(space, &mut ids, rel).await?;
+                }
+                Action::CreateLocalFolder(rel) => fs::create_dir_all(local.join(rel))?,
+                Action::Upload(rel) => {
+                    let folder_id = self.ensure_folder(space, &mut ids, parent_rel(rel)).await?;
+                    let options = UploadOptions {
+                        overwrite: true,
+                        ..options.upload.clone()
+                    };
+                    self.upload(space, &local.join(rel), &folder_id, &options)
+                        .await?;
+                }
+                Action::Download(rel) => {
+                    let file = &ids.files[rel];
+                    let dest = local.join(rel);
+                    if let Some(parent) = dest.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    self.download(space, file, &dest, &options.download).await?;
+                    index.md5(local, rel)?;
+                }
+                Action::DeleteRemote(rel) => {
+                    remote_deletes.push(TaskInfo::from(&ids.files[rel]));
+                }
+                Action::DeleteRemoteFolder(rel) => remote_deletes.push(TaskInfo {
+                    file_id: ids.folders[rel].clone(),
+                    file_name: rel.rsplit('/').next().unwrap_or(rel).to_string(),
+                    is_folder: 1,
+                }),
+                Action::DeleteLocal(rel) => fs::remove_file(local.join(rel))?,
+                Action::DeleteLocalFolder(rel) => fs::remove_dir_all(local.join(rel))?,
+            }
+        }
+        if !remote_deletes.is_empty() {
+            let res = self.delete_items(space, &remote_deletes).await?;
+            if !res.is_success() {
+                bail!("{}个文件删除失败", res.failed_count);
+            }
+        }
+        Ok(report)
+    }
+
+    /// 遍历网盘文件夹
+    async fn remote_tree(&self, space: &Space, root_id: &str) -> Result<(Tree, RemoteIds)> {
+        let mut tree = Tree::default();
+        let mut ids = RemoteIds::default();
+        ids.folders.insert(String::new(), root_id.to_string());
+        let mut pending = vec![(String::new(), root_id.to_string())];
+        while let Some((dir, folder_id)) = pending.pop() {
+            let list = self.list_all(space, &folder_id).await?;
+            for folder in list.folders {
+                let rel = join_rel(&dir, &folder.name);
+                tree.folders.insert(rel.clone());
+                ids.folders.insert(rel.clone(), folder.id.clone());
+                pending.push((rel, folder.id));
+            }
+            for file in list.files {
+                let rel = join_rel(&dir, &file.name);
+                tree.files.insert(
+                    rel.clone(),
+                    FileDigest {
+                        size: file.size,
+                        md5: file.md5.clone(),
+                    },
+                );
+                ids.files.insert(rel, file);
+            }
+        }
+        Ok((tree, ids))
+    }
+
+    /// 获取相对路径对应的网盘文件夹id，不存在时逐级创建
+    async fn ensure_folder(&self, space: &Space, ids: &mut RemoteIds, rel: &str) -> Result<String> {
+        if let Some(id) = ids.folders.get(rel) {
+            return Ok(id.clone());
+        }
+        let (parent, name) = rel.rsplit_once('/').unwrap_or(("", rel));
+        let parent_id = Box::pin(self.ensure_folder(space, ids, parent)).await?;
+        let folder = self.create_folder(space, &parent_id, name).await?;
+        ids.folders.insert(rel.to_string(), folder.id.clone());
+        Ok(folder.id)
+    }
+}
+
+/// 本地目录下的默认索引文件
+pub fn default_index_path(local: &Path) -> PathBuf {
+    local.join(INDEX_FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::const_val::ROOT_FOLDER_ID;
+    use crate::mock::{file_json, folder_json, MockCloud};
+    use serde_json::{json, Value};
+    use wiremock::matchers::{body_string_contains, method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+
+    fn tree(files: &[(&str, &str)], folders: &[&str]) -> Tree {
+        Tree {
+            files: files
+                .iter()
+                .map(|(rel, md5)| {
+                    (
+                        rel.to_string(),
+                        FileDigest {
+                            size: md5.len() as u64,
+                            md5: md5.to_string(),
+                        },
+                    )
+                })
+                .collect(),
+            folders: folders.iter().map(|rel| rel.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let local = tree(
+            &[("a.txt", "AA"), ("docs/b.txt", "BB"), ("docs/c.txt", "C")],
+            &["docs", "empty"],
+        );
+        let remote = tree(
+            &[
+                ("a.txt", "aa"),
+                ("docs/b.txt", "B0"),
+                ("old.txt", "O"),
+                ("tmp/d.txt", "D"),
+            ],
+            &["docs", "tmp"],
+        );
+
+        let (actions, unchanged) = plan(&local, &remote, Direction::Upload, false);
+        assert_eq!(unchanged, 1);
+        assert_eq!(
+            actions,
+            [
+                Action::CreateRemoteFolder("empty".to_string()),
+                Action::Upload("docs/b.txt".to_string()),
+                Action::Upload("docs/c.txt".to_string()),
+            ]
+        );
+
+        // tmp下的文件随文件夹一起删除
+        let (actions, _) = plan(&local, &remote, Direction::Upload, true);
+        assert_eq!(
+            actions[3..],
+            [
+                Action::DeleteRemoteFolder("tmp".to_string()),
+                Action::DeleteRemote("old.txt".to_string()),
+            ]
+        );
+
+        let (actions, _) = plan(&local, &remote, Direction::Download, true);
+        assert_eq!(
+            actions,
+            [
+                Action::CreateLocalFolder("tmp".to_string()),
+                Action::Download("docs/b.txt".to_string()),
+                Action::Download("old.txt".to_string()),
+                Action::Download("tmp/d.txt".to_string()),
+                Action::DeleteLocalFolder("empty".to_string()),
+                Action::DeleteLocal("docs/c.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_local_tree_and_index() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("cloud-189-sync-{}", util::timestamp()));
+        fs::create_dir_all(root.join("docs"))?;
+        fs::write(root.join("a.txt"), b"hello")?;
+        fs::write(root.join("docs/b.txt"), b"world")?;
+        let index = SyncIndex::open(&default_index_path(&root))?;
+        // sqlite事务期间的日志文件
+        fs::write(root.join(format!("{INDEX_FILE_NAME}-journal")), b"")?;
+        fs::write(root.join(format!("{INDEX_FILE_NAME}-wal")), b"")?;
+
+        let tree = local_tree(&root, &index)?;
+        assert_eq!(tree.folders.iter().collect::<Vec<_>>(), ["docs"]);
+        assert_eq!(tree.files.len(), 2);
+        assert_eq!(
+            tree.files["a.txt"].md5,
+            format!("{:X}", md5::compute("hello"))
+        );
+
+        // 内容和修改时间变化后重新计算
+        fs::write(root.join("a.txt"), b"hello!")?;
+        let tree = local_tree(&root, &index)?;
+        assert_eq!(
+            tree.files["a.txt"].md5,
+            format!("{:X}", md5::compute("hello!"))
+        );
+
+        fs::remove_file(root.join("docs/b.txt"))?;
+        let tree = local_tree(&root, &index)?;
+        index.retain(&tree.files)?;
+        let count: i64 = index
+            .conn
+            .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))?;
+        assert_eq!(count, 1);
+
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    fn temp_root(name: &str) -> Result<PathBuf> {
+        let root = std::env::temp_dir().join(format!(
+            "cloud-189-sync-{name}-{:016x}",
+            rand::random::<u64>()
+        ));
+        fs::create_dir_all(&root)?;
+        Ok(root)
+    }
+
+    /// 网盘上的`/backup`，id为100
+    async fn mount_backup(mock: &MockCloud, files: Value, folders: Value) {
+        mock.mount_list_files(
+            ROOT_FOLDER_ID,
+            json!([]),
+            json!([folder_json(100, "backup")]),
+        )
+        .await;
+        mock.mount_list_files("100", files, folders).await;
+    }
+
+    #[tokio::test]
+    async fn test_sync_upload() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mount_backup(
+            &mock,
+            json!([
+                file_json(200, "a.txt", b"hello"),
+                file_json(201, "old.txt", b"old")
+            ]),
+            json!([folder_json(101, "tmp")]),
+        )
+        .await;
+        mock.mount_list_files("101", json!([file_json(202, "d.txt", b"d")]), json!([]))
+            .await;
+        for (name, id) in [("docs", 102), ("empty", 103)] {
+            Mock::given(method("POST"))
+                .and(path("/api/open/file/createFolder.action"))
+                .and(body_string_contains("parentFolderId=100"))
+                .and(body_string_contains(format!("folderName={name}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(folder_json(id, name)))
+                .expect(1)
+                .mount(&mock.server)
+                .await;
+        }
+        mock.mount_upload(false, "").await;
+        mock.mount_batch_task("DELETE", &[4]).await;
+
+        let root = temp_root("upload")?;
+        fs::create_dir_all(root.join("docs"))?;
+        fs::create_dir_all(root.join("empty"))?;
+        fs::write(root.join("a.txt"), b"hello")?;
+        fs::write(root.join("docs/b.txt"), b"world")?;
+        // 中断的下载留下的临时文件不上传
+        fs::write(root.join("c.txt.part"), b"")?;
+        fs::write(root.join("c.txt.part.json"), b"{}")?;
+        let index = SyncIndex::open_in_memory()?;
+        let options = SyncOptions {
+            direction: Direction::Upload,
+            delete: true,
+            ..Default::default()
+        };
+        let report = mock
+            .client()
+            .sync(&Space::Personal, &root, "/backup", &index, &options)
+            .await?;
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(
+            report.actions,
+            [
+                Action::CreateRemoteFolder("docs".to_string()),
+                Action::CreateRemoteFolder("empty".to_string()),
+                Action::Upload("docs/b.txt".to_string()),
+                Action::DeleteRemoteFolder("tmp".to_string()),
+                Action::DeleteRemote("old.txt".to_string()),
+            ]
+        );
+        assert_eq!(
+            mock.request_count("/upload/person/commitMultiUploadFile")
+                .await,
+            1
+        );
+
+        // 文件夹和文件在同一个批量任务中删除
+        let requests = mock.server.received_requests().await.unwrap();
+        let create = requests
+            .iter()
+            .find(|request| request.url.path() == "/web/api/open/batch/createBatchTask.action")
+            .unwrap();
+        let task_infos = url::form_urlencoded::parse(&create.body)
+            .find(|(key, _)| key == "taskInfos")
+            .unwrap()
+            .1;
+        let task_infos = serde_json::from_str::<Value>(&task_infos)?;
+        assert_eq!(
+            task_infos,
+            json!([
+                {"fileId": "101", "fileName": "tmp", "isFolder": 1},
+                {"fileId": "201", "fileName": "old.txt", "isFolder": 0},
+            ])
+        );
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_download() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mount_backup(
+            &mock,
+            json!([file_json(200, "a.txt", b"hello")]),
+            json!([folder_json(101, "docs"), folder_json(102, "empty")]),
+        )
+        .await;
+        mock.mount_list_files("101", json!([file_json(201, "b.txt", b"world")]), json!([]))
+            .await;
+        mock.mount_list_files("102", json!([]), json!([])).await;
+        Mock::given(method("GET"))
+            .and(path("/api/open/file/getFileDownloadUrl.action"))
+            .and(query_param("fileId", "201"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "fileDownloadUrl": format!("{}/oss/b.txt", mock.server.uri()),
+            })))
+            .expect(1)
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/oss/b.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"world".to_vec()))
+            .mount(&mock.server)
+            .await;
+
+        let root = temp_root("download")?;
+        fs::write(root.join("a.txt"), b"hello")?;
+        fs::write(root.join("stale.txt"), b"stale")?;
+        fs::write(root.join("c.txt.part"), b"")?;
+        fs::write(root.join("c.txt.part.json"), b"{}")?;
+        let index = SyncIndex::open_in_memory()?;
+        let options = SyncOptions {
+            direction: Direction::Download,
+            delete: true,
+            ..Default::default()
+        };
+        let report = mock
+            .client()
+            .sync(&Space::Personal, &root, "/backup", &index, &options)
+            .await?;
+        assert_eq!(
+            report.actions,
+            [
+                Action::CreateLocalFolder("docs".to_string()),
+                Action::CreateLocalFolder("empty".to_string()),
+                Action::Download("docs/b.txt".to_string()),
+                Action::DeleteLocal("stale.txt".to_string()),
+            ]
+        );
+        assert_eq!(fs::read(root.join("docs/b.txt"))?, b"world");
+        assert!(root.join("empty").is_dir());
+        assert!(!root.join("stale.txt").exists());
+        // 临时文件不属于同步的文件，不会被删除
+        assert!(root.join("c.txt.part").exists());
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
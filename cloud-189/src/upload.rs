@@ -0,0 +1,473 @@
+use crate::client::CloudClient;
+use crate::file::Space;
+use crate::util;
+use anyhow::{anyhow, Result};
+use base64::prelude::*;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, SeekFrom};
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// 默认分片大小
+const SLICE_SIZE: u64 = 10 * 1024 * 1024;
+/// 单个文件的最大分片数，超过时增大分片
+const MAX_SLICES: u64 = 999;
+
+/// 上传参数
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    /// 同时上传的分片数
+    pub threads: usize,
+    /// 存在同名文件时覆盖，否则由网盘自动重命名
+    pub overwrite: bool,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            threads: 3,
+            overwrite: false,
+        }
+    }
+}
+
+/// 上传完成的文件
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    #[serde(rename = "userFileId", deserialize_with = "util::de_string_or_number")]
+    pub id: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub file_md5: String,
+}
+
+/// 文件和每个分片的md5
+#[derive(Debug)]
+struct FileHash {
+    size: u64,
+    slice_size: u64,
+    /// 大写十六进制
+    file_md5: String,
+    slice_md5s: Vec<[u8; 16]>,
+}
+
+impl FileHash {
+    /// 读取一遍文件，同时计算文件和分片的md5
+    fn compute(path: &Path, slice_size: u64) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut file_context = md5::Context::new();
+        let mut slice_md5s = Vec::new();
+        let mut buf = vec![0; slice_size as usize];
+        loop {
+            let mut len = 0;
+            while len < buf.len() {
+                let n = file.read(&mut buf[len..])?;
+                if n == 0 {
+                    break;
+                }
+                len += n;
+            }
+            // 空文件也有一个分片
+            if len == 0 && !slice_md5s.is_empty() {
+                break;
+            }
+            file_context.consume(&buf[..len]);
+            slice_md5s.push(md5::compute(&buf[..len]).0);
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(Self {
+            size,
+            slice_size,
+            file_md5: format!("{:X}", file_context.finalize()),
+            slice_md5s,
+        })
+    }
+
+    /// 只有一个分片时为文件md5，否则为各分片md5按行拼接后的md5
+    fn slice_md5(&self) -> String {
+        if self.slice_md5s.len() == 1 {
+            return self.file_md5.clone();
+        }
+        let joined = self
+            .slice_md5s
+            .iter()
+            .map(hex::encode_upper)
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("{:X}", md5::compute(joined))
+    }
+
+    /// 分片信息，格式为`分片序号-base64(md5)`，序号从1开始
+    fn part_info(&self, part: usize) -> String {
+        format!(
+            "{}-{}",
+            part,
+            BASE64_STANDARD.encode(self.slice_md5s[part - 1])
+        )
+    }
+
+    /// 分片在文件中的位置和长度
+    fn part_range(&self, part: usize) -> (u64, u64) {
+        let start = (part as u64 - 1) * self.slice_size;
+        (start, self.slice_size.min(self.size - start))
+    }
+}
+
+/// 文件越大分片越大，保证分片数不超过上限
+fn slice_size(file_size: u64) -> u64 {
+    let slices = file_size.div_ceil(SLICE_SIZE);
+    if slices <= MAX_SLICES {
+        SLICE_SIZE
+    } else {
+        SLICE_SIZE * slices.div_ceil(MAX_SLICES)
+    }
+}
+
+/// 解析`key=value&key=value`格式的请求头
+fn parse_request_header(header: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for item in header.split('&').filter(|item| !item.is_empty()) {
+        let (key, value) = item
+            .split_once('=')
+            .ok_or_else(|| anyhow!("无法解析上传请求头: {item}"))?;
+        headers.insert(
+            HeaderName::from_bytes(key.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    Ok(headers)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitUploadResponse {
+    data: InitUploadData,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitUploadData {
+    upload_file_id: String,
+    /// 为1时网盘已有相同文件，可以直接提交
+    #[serde(default)]
+    file_data_exists: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadedPartsResponse {
+    data: UploadedPartsData,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadedPartsData {
+    /// 逗号分隔的分片序号
+    #[serde(default)]
+    uploaded_part_list: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadUrlsResponse {
+    upload_urls: HashMap<String, UploadUrl>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadUrl {
+    #[serde(rename = "requestURL")]
+    request_url: String,
+    request_header: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitResponse {
+    file: UploadedFile,
+}
+
+impl CloudClient {
+    /// 上传本地文件到`parent_folder_id`，网盘已有相同内容的文件时秒传
+    pub async fn upload(
+        &self,
+        space: &Space,
+        local: &Path,
+        parent_folder_id: &str,
+        options: &UploadOptions,
+    ) -> Result<UploadedFile> {
+        let file_name = local
+            .file_name()
+            .ok_or_else(|| anyhow!("无效的文件路径: {}", local.display()))?
+            .to_string_lossy()
+            .to_string();
+        let size = tokio::fs::metadata(local).await?.len();
+        let path = local.to_path_buf();
+        let hash = tokio::task::spawn_blocking(move || FileHash::compute(&path, slice_size(size)))
+            .await??;
+        let slice_md5 = hash.slice_md5();
+
+        let mut params = space.params();
+        params.extend([
+            ("parentFolderId", parent_folder_id.to_string()),
+            ("fileName", file_name),
+            ("fileSize", hash.size.to_string()),
+            ("sliceSize", hash.slice_size.to_string()),
+            ("fileMd5", hash.file_md5.clone()),
+            ("sliceMd5", slice_md5.clone()),
+        ]);
+        let init = self
+            .upload_get::<InitUploadResponse>(space, "/initMultiUpload", &params)
+            .await?
+            .data;
+        if init.file_data_exists != 1 {
+            let uploaded = self.uploaded_parts(space, &init.upload_file_id).await?;
+            let missing = (1..=hash.slice_md5s.len())
+                .filter(|part| !uploaded.contains(part))
+                .collect::<Vec<_>>();
+            futures::stream::iter(missing)
+                .map(|part| self.upload_part(space, local, &hash, &init.upload_file_id, part))
+                .buffer_unordered(options.threads.max(1))
+                .try_collect::<Vec<()>>()
+                .await?;
+        }
+
+        // 3: 覆盖同名文件，1: 自动重命名
+        let opertype = if options.overwrite { "3" } else { "1" };
+        let res = self
+            .upload_get::<CommitResponse>(
+                space,
+                "/commitMultiUploadFile",
+                &[
+                    ("uploadFileId", init.upload_file_id),
+                    ("fileMd5", hash.file_md5.clone()),
+                    ("sliceMd5", slice_md5),
+                    ("lazyCheck", "1".to_string()),
+                    ("isLog", "0".to_string()),
+                    ("opertype", opertype.to_string()),
+                ],
+            )
+            .await?;
+        Ok(res.file)
+    }
+
+    /// 已上传的分片，用于续传
+    async fn uploaded_parts(&self, space: &Space, upload_file_id: &str) -> Result<HashSet<usize>> {
+        let res = self
+            .upload_get::<UploadedPartsResponse>(
+                space,
+                "/getUploadedPartsInfo",
+                &[("uploadFileId", upload_file_id.to_string())],
+            )
+            .await?;
+        Ok(res
+            .data
+            .uploaded_part_list
+            .split(',')
+            .filter_map(|part| part.trim().parse().ok())
+            .collect())
+    }
+
+    async fn upload_part(
+        &self,
+        space: &Space,
+        local: &Path,
+        hash: &FileHash,
+        upload_file_id: &str,
+        part: usize,
+    ) -> Result<()> {
+        let res = self
+            .upload_get::<UploadUrlsResponse>(
+                space,
+                "/getMultiUploadUrls",
+                &[
+                    ("uploadFileId", upload_file_id.to_string()),
+                    ("partInfo", hash.part_info(part)),
+                ],
+            )
+            .await?;
+        let url = res
+            .upload_urls
+            .get(&format!("partNumber_{part}"))
+            .ok_or_else(|| anyhow!("未获取到分片{part}的上传地址"))?;
+
+        let (start, len) = hash.part_range(part);
+        let mut file = tokio::fs::File::open(local).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut data = vec![0; len as usize];
+        file.read_exact(&mut data).await?;
+        self.client
+            .put(&url.request_url)
+            .headers(parse_request_header(&url.request_header)?)
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// 调用上传接口，code不为SUCCESS时返回对应的错误
+    async fn upload_get<T: DeserializeOwned>(
+        &self,
+        space: &Space,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<T> {
+        let request = self
+            .client
+            .get(format!(
+                "{}{}{path}",
+                self.urls.upload,
+                space.upload_prefix()
+            ))
+            .query(params);
+        self.send_json::<T>(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::const_val::ROOT_FOLDER_ID;
+    use crate::mock::MockCloud;
+
+    #[test]
+    fn test_file_hash() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("cloud-189-upload-{}", util::timestamp()));
+        std::fs::write(&path, b"0123456789")?;
+
+        let hash = FileHash::compute(&path, 4)?;
+        assert_eq!(hash.file_md5, "781E5E245D69B566979B86E28D23F2C7");
+        assert_eq!(hash.slice_md5s.len(), 3);
+        assert_eq!(hash.part_range(3), (8, 2));
+        assert_eq!(hash.part_info(1), "1-62L2uTBttXXC1ZaxJ5YnpA==");
+        let joined = ["0123", "4567", "89"]
+            .map(|slice| format!("{:X}", md5::compute(slice)))
+            .join("\n");
+        assert_eq!(hash.slice_md5(), format!("{:X}", md5::compute(joined)));
+
+        // 只有一个分片时分片md5等于文件md5
+        let hash = FileHash::compute(&path, 16)?;
+        assert_eq!(hash.slice_md5(), hash.file_md5);
+
+        std::fs::write(&path, b"")?;
+        let hash = FileHash::compute(&path, 4)?;
+        assert_eq!(hash.slice_md5s.len(), 1);
+        assert_eq!(hash.file_md5, "D41D8CD98F00B204E9800998ECF8427E");
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_slice_size() {
+        assert_eq!(slice_size(1), SLICE_SIZE);
+        assert_eq!(slice_size(SLICE_SIZE * MAX_SLICES), SLICE_SIZE);
+        assert_eq!(slice_size(SLICE_SIZE * MAX_SLICES + 1), SLICE_SIZE * 2);
+    }
+
+    #[test]
+    fn test_parse_request_header() -> Result<()> {
+        let headers = parse_request_header("Authorization=AWS a:b=&x-amz-date=20240501T000000Z")?;
+        assert_eq!(headers["authorization"], "AWS a:b=");
+        assert_eq!(headers["x-amz-date"], "20240501T000000Z");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_instant() -> Result<()> {
+        let mock = MockCloud::start().await;
+        mock.mount_upload(true, "").await;
+        let path = std::env::temp_dir().join(format!("cloud-189-instant-{}", util::timestamp()));
+        std::fs::write(&path, b"0123456789")?;
+
+        let file = mock
+            .client()
+            .upload(
+                &Space::Personal,
+                &path,
+                ROOT_FOLDER_ID,
+                &UploadOptions::default(),
+            )
+            .await?;
+        assert_eq!(file.id, "300");
+        // 秒传时不查询已上传分片，也不上传任何分片
+        assert_eq!(
+            mock.request_count("/upload/person/getUploadedPartsInfo")
+                .await,
+            0
+        );
+        assert_eq!(
+            mock.request_count("/upload/person/getMultiUploadUrls")
+                .await,
+            0
+        );
+        assert_eq!(mock.request_count("/oss/part1").await, 0);
+        assert_eq!(
+            mock.request_count("/upload/person/commitMultiUploadFile")
+                .await,
+            1
+        );
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_resume() -> Result<()> {
+        let mock = MockCloud::start().await;
+        // 第1个分片已上传，只需要上传第2个分片
+        mock.mount_upload(false, "1").await;
+        let path = std::env::temp_dir().join(format!("cloud-189-resume-{}", util::timestamp()));
+        let mut content = vec![b'a'; SLICE_SIZE as usize];
+        content.push(b'b');
+        std::fs::write(&path, &content)?;
+
+        mock.client()
+            .upload(
+                &Space::Personal,
+                &path,
+                ROOT_FOLDER_ID,
+                &UploadOptions::default(),
+            )
+            .await?;
+        assert_eq!(
+            mock.request_count("/upload/person/getUploadedPartsInfo")
+                .await,
+            1
+        );
+        assert_eq!(
+            mock.request_count("/upload/person/getMultiUploadUrls")
+                .await,
+            1
+        );
+        assert_eq!(mock.request_count("/oss/part1").await, 0);
+        let puts = mock
+            .server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|request| request.url.path() == "/oss/part2")
+            .collect::<Vec<_>>();
+        assert_eq!(puts.len(), 1);
+        assert_eq!(puts[0].method, wiremock::http::Method::PUT);
+        assert_eq!(puts[0].body, b"b");
+        assert_eq!(
+            mock.request_count("/upload/person/commitMultiUploadFile")
+                .await,
+            1
+        );
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
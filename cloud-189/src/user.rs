@@ -0,0 +1,71 @@
+use crate::client::CloudClient;
+use anyhow::Result;
+use serde::Deserialize;
+
+/// 账号基本信息
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfo {
+    #[serde(default)]
+    pub user_account: String,
+    #[serde(default)]
+    pub nickname: String,
+}
+
+/// 存储容量，单位字节
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capacity {
+    pub total_size: u64,
+    pub used_size: u64,
+    pub free_size: u64,
+}
+
+/// 个人云和家庭云的容量
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeInfo {
+    #[serde(rename = "cloudCapacityInfo")]
+    pub personal: Capacity,
+    #[serde(rename = "familyCapacityInfo", default)]
+    pub family: Capacity,
+}
+
+impl CloudClient {
+    /// 获取当前登录的账号信息
+    pub async fn get_user_info(&self) -> Result<UserInfo> {
+        let request = self.client.get(format!(
+            "{}/api/portal/v2/getUserBriefInfo.action",
+            self.urls.web
+        ));
+        self.send_json::<UserInfo>(request).await
+    }
+
+    /// 获取用户网盘存储容量信息
+    pub async fn get_user_size_info(&self) -> Result<SizeInfo> {
+        let request = self.client.get(format!(
+            "{}/api/portal/getUserSizeInfo.action",
+            self.urls.web
+        ));
+        self.send_json::<SizeInfo>(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_info() -> Result<()> {
+        let body = r#"{
+            "res_code": 0,
+            "account": "user@189.cn",
+            "cloudCapacityInfo": {"freeSize": 60, "mail189UsedSize": 0, "totalSize": 100, "usedSize": 40},
+            "familyCapacityInfo": {"freeSize": 10, "totalSize": 10, "usedSize": 0}
+        }"#;
+        let info = serde_json::from_str::<SizeInfo>(body)?;
+        assert_eq!(info.personal.used_size, 40);
+        assert_eq!(info.family.total_size, 10);
+        Ok(())
+    }
+}
@@ -1,9 +1,20 @@
-use anyhow::Result;
-use reqwest::Request;
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockEncryptMut, KeyInit};
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use qrcode::render::unicode;
+use qrcode::QrCode;
+use reqwest::{header, Method, Request};
+use serde::{Deserialize, Deserializer};
 use serde_json::Value;
+use sha1::Sha1;
 use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
-use url::Url;
+use url::{form_urlencoded, Url};
 
 pub fn timestamp() -> u128 {
     SystemTime::now()
@@ -33,6 +44,34 @@ pub fn parse_url_params(url: &Url) -> BTreeMap<String, String> {
     url.query_pairs().into_owned().collect()
 }
 
+/// 参与签名的请求参数，GET请求取url参数，其他请求取表单或json请求体
+pub fn parse_request_params(request: &Request) -> BTreeMap<String, String> {
+    if Method::GET == request.method() {
+        return parse_url_params(request.url());
+    }
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| {
+            value
+                .as_bytes()
+                .starts_with(b"application/x-www-form-urlencoded")
+        });
+    if is_form {
+        parse_form_request_body(request)
+    } else {
+        parse_json_request_body(request)
+    }
+}
+
+pub fn parse_form_request_body(request: &Request) -> BTreeMap<String, String> {
+    request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|body| form_urlencoded::parse(body).into_owned().collect())
+        .unwrap_or_default()
+}
+
 pub fn parse_json_request_body(request: &Request) -> BTreeMap<String, String> {
     request
         .body()
@@ -64,7 +103,110 @@ pub fn parse_json_request_body(request: &Request) -> BTreeMap<String, String> {
                 None
             }
         })
-        .unwrap_or(BTreeMap::new())
+        .unwrap_or_default()
+}
+
+/// AES-128-ECB加密，PKCS7填充，返回十六进制
+pub fn aes_ecb_encrypt_hex(data: &str, key: &[u8]) -> Result<String> {
+    let cipher = ecb::Encryptor::<aes::Aes128>::new_from_slice(key)
+        .map_err(|_| anyhow!("aes key长度错误: {}", key.len()))?;
+    Ok(hex::encode(
+        cipher.encrypt_padded_vec_mut::<Pkcs7>(data.as_bytes()),
+    ))
+}
+
+/// HMAC-SHA1签名，返回大写十六进制
+pub fn hmac_sha1_hex(key: &[u8], data: &str) -> String {
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(data.as_bytes());
+    hex::encode_upper(mac.finalize().into_bytes())
+}
+
+/// GMT时间，用作Date请求头
+pub fn http_date() -> String {
+    Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// 随机生成uuid格式的请求id
+pub fn request_id() -> String {
+    let id = format!("{:032x}", rand::random::<u128>());
+    format!(
+        "{}-{}-{}-{}-{}",
+        &id[..8],
+        &id[8..12],
+        &id[12..16],
+        &id[16..20],
+        &id[20..]
+    )
+}
+
+/// 计算文件md5，返回大写十六进制
+pub fn file_md5(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        context.consume(&buf[..len]);
+    }
+    Ok(format!("{:X}", context.finalize()))
+}
+
+/// 接口返回的时间格式
+const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// 兼容数字和字符串格式的id
+pub fn de_string_or_number<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Null => Ok(String::new()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected string or number, got {other}"
+        ))),
+    }
+}
+
+/// 解析`2024-05-01 10:20:30`格式的时间
+pub fn de_date_time<'de, D>(deserializer: D) -> std::result::Result<NaiveDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    NaiveDateTime::parse_from_str(&s, DATE_TIME_FORMAT).map_err(serde::de::Error::custom)
+}
+
+/// 用半高方块字符把二维码渲染成终端文本
+pub fn render_qr_code(content: &str) -> Result<String> {
+    let code = QrCode::new(content.as_bytes())?;
+    // 终端多为深色背景，反色后手机才能识别
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build())
+}
+
+/// 按1024进制格式化文件大小
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
 }
 
 #[cfg(test)]
@@ -90,6 +232,23 @@ mod tests {
         assert_eq!(signature, "ce788ff9145c2260534889c454d437b8");
     }
 
+    #[test]
+    fn test_aes_and_hmac() -> Result<()> {
+        assert_eq!(
+            aes_ecb_encrypt_hex("parentFolderId=-11&fileName=a.txt", b"0123456789abcdef")?,
+            "f7d24fd17610395e39f995e8035c1e7aa256bbad19e800590d5155fa20d89dc9cd363d350c68d0ac4b51d0832b07d459"
+        );
+        assert!(aes_ecb_encrypt_hex("a=1", b"short").is_err());
+        assert_eq!(
+            hmac_sha1_hex(
+                b"secret",
+                "SessionKey=k&Operate=GET&RequestURI=/person/initMultiUpload&Date=Mon, 01 Jan 2024 00:00:00 GMT&params=ab"
+            ),
+            "1BC6D2A7EBC109CC90DEE9D4021CBA594D4DDE36"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_parse_url_params() -> Result<()> {
         let url_str = "https://example.com/path?name=Alice&age=30&active=true";
@@ -98,4 +257,30 @@ mod tests {
         assert_eq!(sorted, "active=true&age=30&name=Alice");
         Ok(())
     }
+
+    #[test]
+    fn test_parse_request_params() -> Result<()> {
+        let client = reqwest::Client::new();
+        let request = client
+            .post("https://example.com/path?ignored=1")
+            .form(&[("type", "COPY"), ("taskInfos", r#"[{"fileId":"1"}]"#)])
+            .build()?;
+        let sorted = sort_parameter(parse_request_params(&request));
+        assert_eq!(sorted, r#"taskInfos=[{"fileId":"1"}]&type=COPY"#);
+
+        let request = client
+            .post("https://example.com/path")
+            .json(&serde_json::json!({"folderId": -11, "name": "docs"}))
+            .build()?;
+        let sorted = sort_parameter(parse_request_params(&request));
+        assert_eq!(sorted, "folderId=-11&name=docs");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
 }
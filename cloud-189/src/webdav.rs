@@ -0,0 +1,1183 @@
+use crate::batch::TaskInfo;
+use crate::client::CloudClient;
+use crate::error::Cloud189Error;
+use crate::file::{split_path, CloudFile, Entry, FileList, Space};
+use crate::upload::UploadOptions;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::{Buf, Bytes, BytesMut};
+use chrono::{FixedOffset, NaiveDateTime};
+use dav_server::davpath::DavPath;
+use dav_server::fakels::FakeLs;
+use dav_server::fs::{
+    DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsError, FsFuture, FsResult, FsStream,
+    OpenOptions, ReadDirMeta,
+};
+use dav_server::DavHandler;
+use futures::{FutureExt, StreamExt};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use reqwest::{header, Client, Response, StatusCode};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tracing::warn;
+
+/// 文件夹列表默认缓存时间
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// WebDAV用到的网盘操作，按文件夹id和文件id访问
+#[async_trait]
+pub trait Drive: Send + Sync {
+    fn root_folder_id(&self) -> &str;
+
+    /// 文件夹下的全部文件和子文件夹
+    async fn list(&self, folder_id: &str) -> Result<FileList>;
+
+    /// 文件下载地址，需要支持Range请求
+    async fn download_url(&self, file_id: &str) -> Result<String>;
+
+    /// 上传本地文件到文件夹，覆盖同名文件
+    async fn upload(&self, local: &std::path::Path, folder_id: &str) -> Result<()>;
+
+    async fn create_folder(&self, parent_id: &str, name: &str) -> Result<()>;
+
+    async fn rename(&self, entry: &Entry, name: &str) -> Result<()>;
+
+    async fn move_to(&self, entry: &Entry, folder_id: &str) -> Result<()>;
+
+    /// 删除到回收站
+    async fn delete(&self, entry: &Entry) -> Result<()>;
+}
+
+/// 个人云或家庭云
+pub struct CloudDrive {
+    client: CloudClient,
+    space: Space,
+}
+
+impl CloudDrive {
+    pub fn new(client: CloudClient, space: Space) -> Self {
+        Self { client, space }
+    }
+}
+
+#[async_trait]
+impl Drive for CloudDrive {
+    fn root_folder_id(&self) -> &str {
+        self.space.root_folder_id()
+    }
+
+    async fn list(&self, folder_id: &str) -> Result<FileList> {
+        self.client.list_all(&self.space, folder_id).await
+    }
+
+    async fn download_url(&self, file_id: &str) -> Result<String> {
+        self.client.get_download_url(&self.space, file_id).await
+    }
+
+    async fn upload(&self, local: &std::path::Path, folder_id: &str) -> Result<()> {
+        let options = UploadOptions {
+            overwrite: true,
+            ..Default::default()
+        };
+        self.client
+            .upload(&self.space, local, folder_id, &options)
+            .await?;
+        Ok(())
+    }
+
+    async fn create_folder(&self, parent_id: &str, name: &str) -> Result<()> {
+        self.client
+            .create_folder(&self.space, parent_id, name)
+            .await?;
+        Ok(())
+    }
+
+    async fn rename(&self, entry: &Entry, name: &str) -> Result<()> {
+        match entry {
+            Entry::File(file) => self.client.rename_file(&self.space, &file.id, name).await,
+            Entry::Folder(folder) => {
+                self.client
+                    .rename_folder(&self.space, &folder.id, name)
+                    .await
+            }
+        }
+    }
+
+    async fn move_to(&self, entry: &Entry, folder_id: &str) -> Result<()> {
+        let res = self
+            .client
+            .move_items(&self.space, &[TaskInfo::from(entry)], folder_id)
+            .await?;
+        if !res.is_success() {
+            bail!("移动失败: {}", entry.name());
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, entry: &Entry) -> Result<()> {
+        let res = self
+            .client
+            .delete_items(&self.space, &[TaskInfo::from(entry)])
+            .await?;
+        if !res.is_success() {
+            bail!("删除失败: {}", entry.name());
+        }
+        Ok(())
+    }
+}
+
+/// 缓存的文件夹列表
+struct Listing {
+    id: String,
+    list: FileList,
+    fetched_at: Instant,
+}
+
+/// 把网盘映射为WebDAV文件系统，文件夹列表缓存一段时间，修改时失效
+#[derive(Clone)]
+pub struct CloudFs {
+    drive: Arc<dyn Drive>,
+    http: Client,
+    cache: Arc<Mutex<HashMap<String, Arc<Listing>>>>,
+    cache_ttl: Duration,
+}
+
+impl CloudFs {
+    pub fn new(drive: impl Drive + 'static) -> Self {
+        Self {
+            drive: Arc::new(drive),
+            http: Client::new(),
+            cache: Arc::default(),
+            cache_ttl: CACHE_TTL,
+        }
+    }
+
+    pub fn with_cache_ttl(self, cache_ttl: Duration) -> Self {
+        Self { cache_ttl, ..self }
+    }
+
+    /// 处理WebDAV请求的handler
+    pub fn handler(self) -> DavHandler {
+        DavHandler::builder()
+            .filesystem(Box::new(self))
+            // Finder等客户端要求支持LOCK
+            .locksystem(FakeLs::new())
+            .build_handler()
+    }
+
+    /// 获取文件夹列表，缓存过期或文件夹id变化时重新获取
+    async fn listing(&self, path: &str, id: &str) -> FsResult<Arc<Listing>> {
+        if let Some(listing) = self.cache.lock().unwrap().get(path)
+            && listing.id == id
+            && listing.fetched_at.elapsed() < self.cache_ttl
+        {
+            return Ok(listing.clone());
+        }
+        let list = self.drive.list(id).await.map_err(fs_error)?;
+        let listing = Arc::new(Listing {
+            id: id.to_string(),
+            list,
+            fetched_at: Instant::now(),
+        });
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), listing.clone());
+        Ok(listing)
+    }
+
+    /// 从根目录逐级获取文件夹列表
+    async fn folder(&self, path: &str) -> FsResult<Arc<Listing>> {
+        let mut current = String::new();
+        let mut listing = self.listing("/", self.drive.root_folder_id()).await?;
+        for name in split_path(path) {
+            let id = listing
+                .list
+                .find_folder(name)
+                .ok_or(FsError::NotFound)?
+                .id
+                .clone();
+            current = format!("{current}/{name}");
+            listing = self.listing(&current, &id).await?;
+        }
+        Ok(listing)
+    }
+
+    /// 获取文件或文件夹，同时返回所在文件夹的列表，根目录返回`None`
+    async fn entry(&self, path: &str) -> FsResult<Option<(Arc<Listing>, Entry)>> {
+        let Some((parent, name)) = split_parent(path) else {
+            return Ok(None);
+        };
+        let listing = self.folder(&parent).await?;
+        let entry = match listing.list.find_folder(name) {
+            Some(folder) => Entry::Folder(folder.clone()),
+            None => Entry::File(
+                listing
+                    .list
+                    .find_file(name)
+                    .ok_or(FsError::NotFound)?
+                    .clone(),
+            ),
+        };
+        Ok(Some((listing, entry)))
+    }
+
+    /// 清除路径及其下所有文件夹的缓存
+    fn invalidate(&self, path: &str) {
+        let path = normalize(path);
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        self.cache
+            .lock()
+            .unwrap()
+            .retain(|key, _| *key != path && !key.starts_with(&prefix));
+    }
+
+    async fn open_write(&self, path: &str, options: &OpenOptions) -> FsResult<Box<dyn DavFile>> {
+        if options.append {
+            return Err(FsError::NotImplemented);
+        }
+        let (parent, name) = split_parent(path).ok_or(FsError::Forbidden)?;
+        let listing = self.folder(&parent).await?;
+        if listing.list.find_folder(name).is_some() {
+            return Err(FsError::Forbidden);
+        }
+        if options.create_new && listing.list.find_file(name).is_some() {
+            return Err(FsError::Exists);
+        }
+        // 上传时用本地文件名作为网盘文件名，每个文件单独放一个临时目录
+        let dir = create_temp_dir()
+            .await
+            .map_err(|err| fs_error(err.into()))?;
+        let local = dir.join(name);
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&local)
+            .await
+            .map_err(|err| fs_error(err.into()))?;
+        Ok(Box::new(WriteFile {
+            fs: self.clone(),
+            folder_path: parent,
+            folder_id: listing.id.clone(),
+            dir,
+            local,
+            file,
+            size: 0,
+            dirty: true,
+        }))
+    }
+}
+
+/// 新建只有当前用户可以访问的临时目录，名称随机，已存在时失败而不是沿用
+async fn create_temp_dir() -> std::io::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("cloud-189-webdav-{:016x}", rand::random::<u64>()));
+    let mut builder = tokio::fs::DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(&dir).await?;
+    Ok(dir)
+}
+
+/// 统一为`/a/b`的形式，根目录为`/`
+fn normalize(path: &str) -> String {
+    format!("/{}", split_path(path).join("/"))
+}
+
+/// 拆分出所在文件夹和名称，根目录返回`None`
+fn split_parent(path: &str) -> Option<(String, &str)> {
+    let mut names = split_path(path);
+    let name = names.pop()?;
+    Some((format!("/{}", names.join("/")), name))
+}
+
+fn dav_path(path: &DavPath) -> String {
+    path.as_pathbuf().to_string_lossy().to_string()
+}
+
+/// 按网盘接口的错误类型返回对应的状态码，错误信息输出到日志
+fn fs_error(err: anyhow::Error) -> FsError {
+    warn!("WebDAV请求网盘失败: {err:#}");
+    match err.downcast_ref::<Cloud189Error>() {
+        Some(Cloud189Error::NotFound(_)) => FsError::NotFound,
+        Some(Cloud189Error::AlreadyExists(_)) => FsError::Exists,
+        Some(Cloud189Error::QuotaExceeded(_)) => FsError::InsufficientStorage,
+        _ => FsError::GeneralFailure,
+    }
+}
+
+/// 网盘返回的是北京时间
+fn system_time(time: &NaiveDateTime) -> SystemTime {
+    let offset = FixedOffset::east_opt(8 * 60 * 60).unwrap();
+    time.and_local_timezone(offset)
+        .single()
+        .map(SystemTime::from)
+        .unwrap_or(UNIX_EPOCH)
+}
+
+#[derive(Debug, Clone)]
+struct Meta {
+    size: u64,
+    modified: SystemTime,
+    is_dir: bool,
+    md5: String,
+}
+
+impl Meta {
+    fn root() -> Self {
+        Self {
+            size: 0,
+            modified: UNIX_EPOCH,
+            is_dir: true,
+            md5: String::new(),
+        }
+    }
+}
+
+impl From<&Entry> for Meta {
+    fn from(entry: &Entry) -> Self {
+        match entry {
+            Entry::File(file) => Self {
+                size: file.size,
+                modified: system_time(&file.last_op_time),
+                is_dir: false,
+                md5: file.md5.clone(),
+            },
+            Entry::Folder(folder) => Self {
+                size: 0,
+                modified: system_time(&folder.last_op_time),
+                is_dir: true,
+                md5: String::new(),
+            },
+        }
+    }
+}
+
+impl DavMetaData for Meta {
+    fn len(&self) -> u64 {
+        self.size
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        Ok(self.modified)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn etag(&self) -> Option<String> {
+        if self.md5.is_empty() {
+            let modified = self.modified.duration_since(UNIX_EPOCH).ok()?;
+            Some(format!("{:x}-{:x}", modified.as_secs(), self.size))
+        } else {
+            Some(self.md5.to_lowercase())
+        }
+    }
+}
+
+struct DirEntry {
+    name: String,
+    meta: Meta,
+}
+
+impl DavDirEntry for DirEntry {
+    fn name(&self) -> Vec<u8> {
+        self.name.as_bytes().to_vec()
+    }
+
+    fn metadata(&self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        let meta = self.meta.clone();
+        async move { Ok(Box::new(meta) as Box<dyn DavMetaData>) }.boxed()
+    }
+}
+
+/// 正在读取的Range请求，从`pos`开始
+struct RangeStream {
+    pos: u64,
+    buf: BytesMut,
+    response: Response,
+}
+
+/// 只读打开的网盘文件，按读取位置向下载地址发Range请求
+struct ReadFile {
+    fs: CloudFs,
+    file: CloudFile,
+    pos: u64,
+    url: Option<String>,
+    stream: Option<RangeStream>,
+}
+
+impl fmt::Debug for ReadFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadFile")
+            .field("file", &self.file.name)
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+impl ReadFile {
+    /// 从`pos`开始请求到文件末尾，连续读取时复用同一个响应
+    async fn open_stream(&mut self, pos: u64) -> Result<RangeStream> {
+        let url = match &self.url {
+            Some(url) => url.clone(),
+            None => self.fs.drive.download_url(&self.file.id).await?,
+        };
+        self.url = Some(url.clone());
+        let response = self
+            .fs
+            .http
+            .get(&url)
+            .header(header::RANGE, format!("bytes={pos}-"))
+            .send()
+            .await?
+            .error_for_status()?;
+        if response.status() != StatusCode::PARTIAL_CONTENT && pos != 0 {
+            bail!("服务器不支持分段下载: {}", response.status());
+        }
+        Ok(RangeStream {
+            pos,
+            buf: BytesMut::new(),
+            response,
+        })
+    }
+
+    async fn read(&mut self, count: usize) -> Result<Bytes> {
+        let want = (self.file.size.saturating_sub(self.pos)).min(count as u64) as usize;
+        if want == 0 {
+            return Ok(Bytes::new());
+        }
+        let mut stream = match self.stream.take() {
+            Some(stream) if stream.pos == self.pos => stream,
+            _ => self.open_stream(self.pos).await?,
+        };
+        while stream.buf.len() < want {
+            match stream.response.chunk().await? {
+                Some(chunk) => stream.buf.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        if stream.buf.is_empty() {
+            bail!("{}的数据不完整", self.file.name);
+        }
+        let bytes = stream.buf.split_to(want.min(stream.buf.len())).freeze();
+        stream.pos += bytes.len() as u64;
+        self.pos = stream.pos;
+        self.stream = Some(stream);
+        Ok(bytes)
+    }
+}
+
+impl DavFile for ReadFile {
+    fn metadata(&mut self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        let meta = Meta::from(&Entry::File(self.file.clone()));
+        async move { Ok(Box::new(meta) as Box<dyn DavMetaData>) }.boxed()
+    }
+
+    fn write_buf(&mut self, _buf: Box<dyn Buf + Send>) -> FsFuture<'_, ()> {
+        async move { Err(FsError::Forbidden) }.boxed()
+    }
+
+    fn write_bytes(&mut self, _buf: Bytes) -> FsFuture<'_, ()> {
+        async move { Err(FsError::Forbidden) }.boxed()
+    }
+
+    fn read_bytes(&mut self, count: usize) -> FsFuture<'_, Bytes> {
+        async move { self.read(count).await.map_err(fs_error) }.boxed()
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.file.size.checked_add_signed(offset),
+        };
+        async move {
+            self.pos = pos.ok_or(FsError::GeneralFailure)?;
+            Ok(self.pos)
+        }
+        .boxed()
+    }
+
+    fn flush(&mut self) -> FsFuture<'_, ()> {
+        async move { Ok(()) }.boxed()
+    }
+}
+
+/// 写入的文件先保存到临时文件，flush时上传
+struct WriteFile {
+    fs: CloudFs,
+    folder_path: String,
+    folder_id: String,
+    /// 临时目录，关闭时删除
+    dir: PathBuf,
+    local: PathBuf,
+    file: tokio::fs::File,
+    size: u64,
+    /// 有未上传的写入
+    dirty: bool,
+}
+
+impl fmt::Debug for WriteFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteFile")
+            .field("local", &self.local)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Drop for WriteFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl DavFile for WriteFile {
+    fn metadata(&mut self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        let meta = Meta {
+            size: self.size,
+            modified: SystemTime::now(),
+            is_dir: false,
+            md5: String::new(),
+        };
+        async move { Ok(Box::new(meta) as Box<dyn DavMetaData>) }.boxed()
+    }
+
+    fn write_buf(&mut self, mut buf: Box<dyn Buf + Send>) -> FsFuture<'_, ()> {
+        let bytes = buf.copy_to_bytes(buf.remaining());
+        self.write_bytes(bytes)
+    }
+
+    fn write_bytes(&mut self, buf: Bytes) -> FsFuture<'_, ()> {
+        async move {
+            self.file
+                .write_all(&buf)
+                .await
+                .map_err(|err| fs_error(err.into()))?;
+            self.size += buf.len() as u64;
+            self.dirty = true;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn read_bytes(&mut self, _count: usize) -> FsFuture<'_, Bytes> {
+        async move { Err(FsError::Forbidden) }.boxed()
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
+        async move {
+            // 只支持顺序写入
+            match pos {
+                SeekFrom::Current(0) => Ok(self.size),
+                SeekFrom::Start(pos) if pos == self.size => Ok(pos),
+                _ => Err(FsError::NotImplemented),
+            }
+        }
+        .boxed()
+    }
+
+    fn flush(&mut self) -> FsFuture<'_, ()> {
+        async move {
+            if !self.dirty {
+                return Ok(());
+            }
+            self.file
+                .flush()
+                .await
+                .map_err(|err| fs_error(err.into()))?;
+            let res = self.fs.drive.upload(&self.local, &self.folder_id).await;
+            self.fs.invalidate(&self.folder_path);
+            res.map_err(fs_error)?;
+            self.dirty = false;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+impl DavFileSystem for CloudFs {
+    fn open<'a>(
+        &'a self,
+        path: &'a DavPath,
+        options: OpenOptions,
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
+        async move {
+            let path = dav_path(path);
+            if options.write {
+                return self.open_write(&path, &options).await;
+            }
+            match self.entry(&path).await? {
+                Some((_, Entry::File(file))) => Ok(Box::new(ReadFile {
+                    fs: self.clone(),
+                    file,
+                    pos: 0,
+                    url: None,
+                    stream: None,
+                }) as Box<dyn DavFile>),
+                _ => Err(FsError::Forbidden),
+            }
+        }
+        .boxed()
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a DavPath,
+        _meta: ReadDirMeta,
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        async move {
+            let listing = self.folder(&dav_path(path)).await?;
+            let folders = listing.list.folders.iter().cloned().map(Entry::Folder);
+            let files = listing.list.files.iter().cloned().map(Entry::File);
+            let entries = folders
+                .chain(files)
+                .map(|entry| {
+                    Ok(Box::new(DirEntry {
+                        name: entry.name().to_string(),
+                        meta: Meta::from(&entry),
+                    }) as Box<dyn DavDirEntry>)
+                })
+                .collect::<Vec<_>>();
+            Ok(futures::stream::iter(entries).boxed())
+        }
+        .boxed()
+    }
+
+    fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        async move {
+            let meta = match self.entry(&dav_path(path)).await? {
+                Some((_, entry)) => Meta::from(&entry),
+                None => Meta::root(),
+            };
+            Ok(Box::new(meta) as Box<dyn DavMetaData>)
+        }
+        .boxed()
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let path = dav_path(path);
+            let (parent, name) = split_parent(&path).ok_or(FsError::Exists)?;
+            let listing = self.folder(&parent).await?;
+            if listing.list.find_folder(name).is_some() || listing.list.find_file(name).is_some() {
+                return Err(FsError::Exists);
+            }
+            let res = self.drive.create_folder(&listing.id, name).await;
+            self.invalidate(&parent);
+            res.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn remove_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        self.remove_file(path)
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let path = dav_path(path);
+            let (_, entry) = self.entry(&path).await?.ok_or(FsError::Forbidden)?;
+            let res = self.drive.delete(&entry).await;
+            if let Some((parent, _)) = split_parent(&path) {
+                self.invalidate(&parent);
+            }
+            self.invalidate(&path);
+            res.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let (from, to) = (dav_path(from), dav_path(to));
+            let (from_listing, entry) = self.entry(&from).await?.ok_or(FsError::Forbidden)?;
+            let (to_parent, name) = split_parent(&to).ok_or(FsError::Forbidden)?;
+            let to_listing = self.folder(&to_parent).await?;
+            // 覆盖已存在的目标文件时先改名让出位置，移动成功后再删除，失败时改回原名。
+            // 目标是文件夹时已经被删除
+            let existing = to_listing.list.find_file(name).cloned().map(Entry::File);
+            let res = async {
+                if let Some(existing) = &existing {
+                    let aside = format!(".{name}.{:08x}.cloud-189", rand::random::<u32>());
+                    self.drive.rename(existing, &aside).await?;
+                }
+                let moved = async {
+                    if to_listing.id != from_listing.id {
+                        self.drive.move_to(&entry, &to_listing.id).await?;
+                    }
+                    if name != entry.name() {
+                        self.drive.rename(&entry, name).await?;
+                    }
+                    Ok(())
+                }
+                .await;
+                match (&existing, moved) {
+                    (Some(existing), Ok(())) => self.drive.delete(existing).await,
+                    (Some(existing), Err(err)) => {
+                        let _ = self.drive.rename(existing, name).await;
+                        Err(err)
+                    }
+                    (None, moved) => moved,
+                }
+            }
+            .await;
+            if let Some((from_parent, _)) = split_parent(&from) {
+                self.invalidate(&from_parent);
+            }
+            self.invalidate(&from);
+            self.invalidate(&to_parent);
+            res.map_err(fs_error)
+        }
+        .boxed()
+    }
+}
+
+/// 在`addr`上提供WebDAV服务，没有鉴权，只应监听本机或可信网络
+pub async fn serve(fs: CloudFs, addr: SocketAddr) -> Result<()> {
+    let handler = fs.handler();
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let handler = handler.clone();
+                async move { Ok::<_, Infallible>(handler.handle(request).await) }
+            });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!("WebDAV连接出错: {err}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::const_val::ROOT_FOLDER_ID;
+    use crate::file::CloudFolder;
+    use crate::mock::{file_json, folder_json, MockCloud};
+    use http_body_util::{BodyExt, Full};
+    use serde_json::json;
+    use wiremock::matchers::{header as header_eq, method, path, query_param};
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    /// 文件或文件夹，`data`为`None`时是文件夹
+    struct Node {
+        id: String,
+        parent: String,
+        name: String,
+        data: Option<Vec<u8>>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        nodes: Vec<Node>,
+        next_id: u64,
+        /// 获取列表的次数
+        lists: usize,
+        /// 移动文件时返回错误
+        fail_move: bool,
+    }
+
+    impl State {
+        fn add(&mut self, parent: &str, name: &str, data: Option<Vec<u8>>) {
+            self.next_id += 1;
+            self.nodes.push(Node {
+                id: self.next_id.to_string(),
+                parent: parent.to_string(),
+                name: name.to_string(),
+                data,
+            });
+        }
+
+        fn node(&mut self, id: &str) -> &mut Node {
+            self.nodes.iter_mut().find(|node| node.id == id).unwrap()
+        }
+
+        fn find(&self, path: &str) -> Option<&Node> {
+            let mut parent = "root";
+            let mut found = None;
+            for name in split_path(path) {
+                let node = self
+                    .nodes
+                    .iter()
+                    .find(|node| node.parent == parent && node.name == name)?;
+                parent = &node.id;
+                found = Some(node);
+            }
+            found
+        }
+    }
+
+    /// 内存中的网盘，文件内容由wiremock按Range返回
+    struct MemoryDrive {
+        state: Arc<Mutex<State>>,
+        base_url: String,
+    }
+
+    #[async_trait]
+    impl Drive for MemoryDrive {
+        fn root_folder_id(&self) -> &str {
+            "root"
+        }
+
+        async fn list(&self, folder_id: &str) -> Result<FileList> {
+            let mut state = self.state.lock().unwrap();
+            state.lists += 1;
+            let time = NaiveDateTime::parse_from_str("2024-05-01 10:20:30", "%Y-%m-%d %H:%M:%S")?;
+            let mut list = FileList::default();
+            for node in state.nodes.iter().filter(|node| node.parent == folder_id) {
+                match &node.data {
+                    Some(data) => list.files.push(CloudFile {
+                        id: node.id.clone(),
+                        name: node.name.clone(),
+                        size: data.len() as u64,
+                        md5: format!("{:X}", md5::compute(data)),
+                        last_op_time: time,
+                        create_date: time,
+                    }),
+                    None => list.folders.push(CloudFolder {
+                        id: node.id.clone(),
+                        parent_id: folder_id.to_string(),
+                        name: node.name.clone(),
+                        last_op_time: time,
+                        create_date: time,
+                    }),
+                }
+            }
+            list.count = list.len() as u64;
+            Ok(list)
+        }
+
+        async fn download_url(&self, file_id: &str) -> Result<String> {
+            Ok(format!("{}/files/{file_id}", self.base_url))
+        }
+
+        async fn upload(&self, local: &std::path::Path, folder_id: &str) -> Result<()> {
+            let name = local.file_name().unwrap().to_string_lossy().to_string();
+            let data = std::fs::read(local)?;
+            let mut state = self.state.lock().unwrap();
+            state
+                .nodes
+                .retain(|node| !(node.parent == folder_id && node.name == name));
+            state.add(folder_id, &name, Some(data));
+            Ok(())
+        }
+
+        async fn create_folder(&self, parent_id: &str, name: &str) -> Result<()> {
+            self.state.lock().unwrap().add(parent_id, name, None);
+            Ok(())
+        }
+
+        async fn rename(&self, entry: &Entry, name: &str) -> Result<()> {
+            self.state.lock().unwrap().node(entry.id()).name = name.to_string();
+            Ok(())
+        }
+
+        async fn move_to(&self, entry: &Entry, folder_id: &str) -> Result<()> {
+            let mut state = self.state.lock().unwrap();
+            if state.fail_move {
+                bail!("移动失败: {}", entry.name());
+            }
+            state.node(entry.id()).parent = folder_id.to_string();
+            Ok(())
+        }
+
+        async fn delete(&self, entry: &Entry) -> Result<()> {
+            let mut state = self.state.lock().unwrap();
+            let mut removed = vec![entry.id().to_string()];
+            while let Some(id) = removed.pop() {
+                removed.extend(
+                    state
+                        .nodes
+                        .iter()
+                        .filter(|node| node.parent == id)
+                        .map(|node| node.id.clone()),
+                );
+                state.nodes.retain(|node| node.id != id);
+            }
+            Ok(())
+        }
+    }
+
+    /// 按`/files/<id>`和`Range: bytes=<start>-`返回文件内容
+    struct FileResponder(Arc<Mutex<State>>);
+
+    impl Respond for FileResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let id = request.url.path().trim_start_matches("/files/");
+            let mut state = self.0.lock().unwrap();
+            let data = state.node(id).data.clone().unwrap();
+            let start = request.headers[header::RANGE]
+                .to_str()
+                .unwrap()
+                .trim_start_matches("bytes=")
+                .trim_end_matches('-')
+                .parse::<usize>()
+                .unwrap();
+            ResponseTemplate::new(206).set_body_bytes(data[start..].to_vec())
+        }
+    }
+
+    async fn memory_fs() -> (CloudFs, Arc<Mutex<State>>, MockServer) {
+        let state = Arc::new(Mutex::new(State::default()));
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(FileResponder(state.clone()))
+            .mount(&server)
+            .await;
+        let drive = MemoryDrive {
+            state: state.clone(),
+            base_url: server.uri(),
+        };
+        (CloudFs::new(drive), state, server)
+    }
+
+    async fn request(
+        handler: &DavHandler,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> (StatusCode, String) {
+        let mut builder = hyper::Request::builder().method(method).uri(path);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let request = builder
+            .body(Full::new(Bytes::from(body.to_string())))
+            .unwrap();
+        let response = handler.handle(request).await;
+        let status = response.status();
+        let body = BodyExt::collect(response.into_body()).await.unwrap();
+        let body = body.to_bytes();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    #[tokio::test]
+    async fn test_webdav() {
+        let (fs, state, _server) = memory_fs().await;
+        let handler = fs.handler();
+
+        let (status, _) = request(&handler, "MKCOL", "/docs", &[], "").await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = request(&handler, "PUT", "/docs/a.txt", &[], "hello world").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(
+            state.lock().unwrap().find("/docs/a.txt").unwrap().data,
+            Some(b"hello world".to_vec())
+        );
+
+        let (status, body) = request(&handler, "PROPFIND", "/docs/", &[("Depth", "1")], "").await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body.contains("/docs/a.txt"));
+        assert!(body.contains("<D:getcontentlength>11</D:getcontentlength>"));
+
+        let (status, body) = request(&handler, "GET", "/docs/a.txt", &[], "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "hello world");
+        let (status, body) =
+            request(&handler, "GET", "/docs/a.txt", &[("Range", "bytes=6-")], "").await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, "world");
+
+        let destination = [("Destination", "http://localhost/b.txt")];
+        let (status, _) = request(&handler, "MOVE", "/docs/a.txt", &destination, "").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(state.lock().unwrap().find("/docs/a.txt").is_none());
+        assert!(state.lock().unwrap().find("/b.txt").is_some());
+        let (status, _) = request(&handler, "GET", "/docs/a.txt", &[], "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = request(&handler, "DELETE", "/docs", &[], "").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(state.lock().unwrap().find("/docs").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cloud_drive() {
+        let mock = MockCloud::start().await;
+        mock.mount_list_files(ROOT_FOLDER_ID, json!([]), json!([folder_json(100, "docs")]))
+            .await;
+        mock.mount_list_files(
+            "100",
+            json!([file_json(200, "a.txt", b"hello world")]),
+            json!([]),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/api/open/file/getFileDownloadUrl.action"))
+            .and(query_param("fileId", "200"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "fileDownloadUrl": format!("{}/oss/a.txt", mock.server.uri()),
+            })))
+            .mount(&mock.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/oss/a.txt"))
+            .and(header_eq("range", "bytes=0-"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(b"hello world".to_vec()))
+            .mount(&mock.server)
+            .await;
+        mock.mount_upload(false, "").await;
+        mock.mount_batch_task("MOVE", &[4]).await;
+        // 删除文件夹时先逐个删除其中的文件，列表是固定的，a.txt仍在docs中
+        mock.mount_batch_task("DELETE", &[4, 4]).await;
+        let handler = CloudFs::new(CloudDrive::new(mock.client(), Space::Personal)).handler();
+
+        let (status, body) = request(&handler, "PROPFIND", "/docs/", &[("Depth", "1")], "").await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body.contains("/docs/a.txt"));
+        assert!(body.contains("<D:getcontentlength>11</D:getcontentlength>"));
+
+        let (status, body) = request(&handler, "GET", "/docs/a.txt", &[], "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "hello world");
+
+        let (status, _) = request(&handler, "PUT", "/docs/b.txt", &[], "new").await;
+        assert_eq!(status, StatusCode::CREATED);
+        let requests = mock.server.received_requests().await.unwrap();
+        let part = requests
+            .iter()
+            .find(|request| request.url.path() == "/oss/part1")
+            .unwrap();
+        assert_eq!(part.body, b"new");
+        assert_eq!(
+            mock.request_count("/upload/person/commitMultiUploadFile")
+                .await,
+            1
+        );
+
+        let destination = [("Destination", "http://localhost/a.txt")];
+        let (status, _) = request(&handler, "MOVE", "/docs/a.txt", &destination, "").await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = request(&handler, "DELETE", "/docs", &[], "").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let tasks = mock
+            .server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|request| request.url.path() == "/web/api/open/batch/createBatchTask.action")
+            .map(|request| String::from_utf8(request.body).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(tasks.len(), 3);
+        assert!(tasks[0].contains("type=MOVE"));
+        assert!(tasks[0].contains(&format!("targetFolderId={ROOT_FOLDER_ID}")));
+        assert!(tasks[1..].iter().all(|task| task.contains("type=DELETE")));
+    }
+
+    #[tokio::test]
+    async fn test_move_overwrite() {
+        let (fs, state, _server) = memory_fs().await;
+        {
+            let mut state = state.lock().unwrap();
+            state.add("root", "docs", None);
+            state.add("1", "a.txt", Some(b"new".to_vec()));
+            state.add("root", "b.txt", Some(b"old".to_vec()));
+        }
+        let handler = fs.handler();
+        let names = || {
+            let state = state.lock().unwrap();
+            let mut names = state
+                .nodes
+                .iter()
+                .map(|node| node.name.clone())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let destination = [
+            ("Destination", "http://localhost/b.txt"),
+            ("Overwrite", "T"),
+        ];
+
+        // 移动失败时保留原来的目标文件
+        state.lock().unwrap().fail_move = true;
+        let (status, _) = request(&handler, "MOVE", "/docs/a.txt", &destination, "").await;
+        assert!(status.is_server_error());
+        assert_eq!(names(), ["a.txt", "b.txt", "docs"]);
+        assert_eq!(
+            state.lock().unwrap().find("/b.txt").unwrap().data,
+            Some(b"old".to_vec())
+        );
+
+        state.lock().unwrap().fail_move = false;
+        let (status, _) = request(&handler, "MOVE", "/docs/a.txt", &destination, "").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(names(), ["b.txt", "docs"]);
+        assert_eq!(
+            state.lock().unwrap().find("/b.txt").unwrap().data,
+            Some(b"new".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_listing_cache() {
+        let (fs, state, _server) = memory_fs().await;
+        state.lock().unwrap().add("root", "docs", None);
+        let handler = fs.clone().handler();
+        let lists = || state.lock().unwrap().lists;
+
+        request(&handler, "PROPFIND", "/docs/", &[("Depth", "1")], "").await;
+        let cached = lists();
+        request(&handler, "PROPFIND", "/docs/", &[("Depth", "1")], "").await;
+        assert_eq!(lists(), cached);
+
+        // 写入后所在文件夹重新获取
+        request(&handler, "MKCOL", "/docs/2024", &[], "").await;
+        let (_, body) = request(&handler, "PROPFIND", "/docs/", &[("Depth", "1")], "").await;
+        assert!(body.contains("/docs/2024/"));
+        assert!(lists() > cached);
+
+        // 过期后重新获取
+        let fs = fs.with_cache_ttl(Duration::ZERO);
+        let before = lists();
+        fs.folder("/docs").await.unwrap();
+        assert_eq!(lists(), before + 2);
+    }
+
+    #[tokio::test]
+    async fn test_create_temp_dir() -> Result<()> {
+        let dir = create_temp_dir().await?;
+        let other = create_temp_dir().await?;
+        assert_ne!(other, dir);
+        std::fs::remove_dir(other)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&dir)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        std::fs::remove_dir(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_parent() {
+        assert_eq!(normalize("docs//2024/"), "/docs/2024");
+        assert_eq!(split_parent("/"), None);
+        assert_eq!(split_parent("/a.txt"), Some(("/".to_string(), "a.txt")));
+        assert_eq!(
+            split_parent("/docs/2024/a.txt"),
+            Some(("/docs/2024".to_string(), "a.txt"))
+        );
+    }
+}
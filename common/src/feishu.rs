@@ -0,0 +1,73 @@
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// 发送消息的超时时间
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 发送文本消息到飞书机器人webhook
+pub async fn send_text(url: &str, text: &str) -> Result<()> {
+    let request = Client::new().post(url).timeout(TIMEOUT).json(&json!({
+        "msg_type": "text",
+        "content": {"text": text},
+    }));
+    let res = async {
+        request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await
+    }
+    .await
+    // webhook地址中带有密钥
+    .map_err(reqwest::Error::without_url)?;
+    // 飞书机器人出错时http状态码仍为200
+    match res.get("code").and_then(Value::as_i64) {
+        Some(0) | None => Ok(()),
+        Some(code) => Err(anyhow!(
+            "feishu error {}: {}",
+            code,
+            res.get("msg").and_then(Value::as_str).unwrap_or_default()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_send_text() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_json(json!({
+                "msg_type": "text",
+                "content": {"text": "hello"},
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"code": 0, "msg": "success"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        send_text(&server.uri(), "hello").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_text_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"code": 19001, "msg": "param invalid"})),
+            )
+            .mount(&server)
+            .await;
+        let err = send_text(&server.uri(), "hello").await.unwrap_err();
+        assert!(err.to_string().contains("19001"));
+    }
+}
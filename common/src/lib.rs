@@ -1 +1,2 @@
+pub mod feishu;
 pub mod time;
\ No newline at end of file
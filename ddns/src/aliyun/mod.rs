@@ -0,0 +1,428 @@
+use crate::error::{DdnsError, Result};
+use crate::verify::VerifyTarget;
+use crate::{DdnsClient, IpAddressType, RunArgs};
+use async_trait::async_trait;
+use base64::prelude::*;
+use clap::Parser;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha1::Sha1;
+use std::collections::BTreeMap;
+use std::net::Ipv6Addr;
+use tracing::{debug, info};
+
+/// 云解析api版本
+const API_VERSION: &str = "2015-01-09";
+
+/// Update Aliyun DNS ipv6 address
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Your domain name, e.g. `example.com`.
+    #[arg(short, long)]
+    domain: String,
+    /// Host record, `@` for the domain itself.
+    #[arg(long, default_value = "@")]
+    rr: String,
+    /// RAM AccessKey ID.
+    #[arg(long)]
+    access_key_id: String,
+    /// RAM AccessKey Secret.
+    #[arg(long)]
+    access_key_secret: String,
+    /// Aliyun DNS api endpoint
+    #[arg(long, default_value = "https://alidns.aliyuncs.com")]
+    endpoint: String,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Clone)]
+pub struct Aliyun {
+    /// Your domain name
+    domain: String,
+    /// Host record
+    rr: String,
+    access_key_id: String,
+    access_key_secret: String,
+    /// Aliyun DNS api endpoint
+    endpoint: String,
+    /// Update interval and address watching
+    run: RunArgs,
+    client: Client,
+    /// ipv6 update before
+    record_ipv6: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    code: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DescribeSubDomainRecords {
+    domain_records: DomainRecords,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DomainRecords {
+    record: Vec<DomainRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DomainRecord {
+    record_id: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RecordResponse {
+    record_id: String,
+}
+
+impl From<Args> for Aliyun {
+    fn from(value: Args) -> Self {
+        Aliyun {
+            domain: value.domain,
+            rr: value.rr,
+            access_key_id: value.access_key_id,
+            access_key_secret: value.access_key_secret,
+            endpoint: value.endpoint,
+            run: value.run,
+            client: Client::new(),
+            record_ipv6: String::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl DdnsClient for Aliyun {
+    fn provider_name(&self) -> &'static str {
+        "aliyun"
+    }
+
+    fn support_type(&self) -> Vec<IpAddressType> {
+        vec![IpAddressType::Ipv6]
+    }
+
+    fn run_args(&self) -> &RunArgs {
+        &self.run
+    }
+
+    fn record_name(&self) -> String {
+        self.sub_domain()
+    }
+
+    fn record_ip(&self) -> &str {
+        &self.record_ipv6
+    }
+
+    fn forget_record(&mut self) {
+        self.record_ipv6.clear();
+    }
+
+    fn verify_target(&self) -> VerifyTarget {
+        VerifyTarget {
+            zone: self.domain.clone(),
+            name: self.sub_domain(),
+        }
+    }
+
+    fn host_client(&self, host: &str) -> Option<Box<dyn DdnsClient + Send>> {
+        Some(Box::new(Aliyun {
+            rr: host.to_string(),
+            record_ipv6: String::new(),
+            ..self.clone()
+        }))
+    }
+
+    fn info_log(&self) {
+        info!(domain = %self.domain, rr = %self.rr, interval = self.run.interval, "aliyun client started");
+    }
+
+    fn describe_update(&self, ip: Ipv6Addr) -> String {
+        format!(
+            "{}: DescribeSubDomainRecords SubDomain={} Type=AAAA, then UpdateDomainRecord or AddDomainRecord RR={} Value={}",
+            self.endpoint,
+            self.sub_domain(),
+            self.rr,
+            ip
+        )
+    }
+
+    async fn update_ip(&mut self, address: Ipv6Addr) -> Result<()> {
+        let ipv6 = address.to_string();
+        let ipv6 = ipv6.as_str();
+        if ipv6 == self.record_ipv6 {
+            debug!(domain = %self.domain, rr = %self.rr, ipv6, "address unchanged");
+            return Ok(());
+        }
+        let records = self
+            .request::<DescribeSubDomainRecords>(
+                "DescribeSubDomainRecords",
+                BTreeMap::from([
+                    ("SubDomain".to_string(), self.sub_domain()),
+                    ("Type".to_string(), "AAAA".to_string()),
+                ]),
+            )
+            .await?;
+        match records.domain_records.record.first() {
+            Some(record) if record.value == ipv6 => {
+                debug!(domain = %self.domain, rr = %self.rr, ipv6, "record already up to date");
+            }
+            Some(record) => {
+                self.request::<RecordResponse>(
+                    "UpdateDomainRecord",
+                    BTreeMap::from([
+                        ("RecordId".to_string(), record.record_id.clone()),
+                        ("RR".to_string(), self.rr.clone()),
+                        ("Type".to_string(), "AAAA".to_string()),
+                        ("Value".to_string(), ipv6.to_string()),
+                    ]),
+                )
+                .await?;
+                info!(domain = %self.domain, rr = %self.rr, ipv6, "record updated");
+            }
+            None => {
+                let res = self
+                    .request::<RecordResponse>(
+                        "AddDomainRecord",
+                        BTreeMap::from([
+                            ("DomainName".to_string(), self.domain.clone()),
+                            ("RR".to_string(), self.rr.clone()),
+                            ("Type".to_string(), "AAAA".to_string()),
+                            ("Value".to_string(), ipv6.to_string()),
+                        ]),
+                    )
+                    .await?;
+                info!(domain = %self.domain, rr = %self.rr, ipv6, record_id = %res.record_id, "record added");
+            }
+        }
+        self.record_ipv6 = ipv6.to_string();
+        Ok(())
+    }
+}
+
+impl Aliyun {
+    fn sub_domain(&self) -> String {
+        if self.rr == "@" {
+            self.domain.clone()
+        } else {
+            format!("{}.{}", self.rr, self.domain)
+        }
+    }
+
+    /// 调用云解析RPC接口
+    async fn request<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        mut params: BTreeMap<String, String>,
+    ) -> Result<T> {
+        let common_params = [
+            ("Action", action.to_string()),
+            ("Format", "JSON".to_string()),
+            ("Version", API_VERSION.to_string()),
+            ("AccessKeyId", self.access_key_id.clone()),
+            ("SignatureMethod", "HMAC-SHA1".to_string()),
+            ("SignatureVersion", "1.0".to_string()),
+            ("SignatureNonce", rand::random::<u64>().to_string()),
+            (
+                "Timestamp",
+                chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            ),
+        ];
+        for (key, value) in common_params {
+            params.insert(key.to_string(), value);
+        }
+        let signature = sign(self.access_key_secret.as_str(), "GET", &params);
+        params.insert("Signature".to_string(), signature);
+
+        let response = self
+            .client
+            .get(&self.endpoint)
+            .query(&params)
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(err) => map_error(status, err),
+                Err(_) => DdnsError::from_status(status, body, None),
+            });
+        }
+        Ok(serde_json::from_str(&body).map_err(anyhow::Error::from)?)
+    }
+}
+
+/// 按错误码区分错误类型
+fn map_error(status: StatusCode, err: ErrorResponse) -> DdnsError {
+    let code = err.code.as_str();
+    if code.starts_with("InvalidAccessKeyId")
+        || code.starts_with("Forbidden")
+        || code == "SignatureDoesNotMatch"
+        || code == "IncompleteSignature"
+    {
+        DdnsError::Auth(format!("{}: {}", err.code, err.message))
+    } else if code.starts_with("Throttling") {
+        DdnsError::RateLimited { retry_after: None }
+    } else {
+        DdnsError::Provider {
+            status: status.as_u16(),
+            message: format!("{}: {}", err.code, err.message),
+        }
+    }
+}
+
+/// RPC接口签名：对排序后的参数做HMAC-SHA1
+fn sign(access_key_secret: &str, method: &str, params: &BTreeMap<String, String>) -> String {
+    let canonicalized = params
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                urlencoding::encode(key),
+                urlencoding::encode(value)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("&");
+    let string_to_sign = format!(
+        "{}&{}&{}",
+        method,
+        urlencoding::encode("/"),
+        urlencoding::encode(canonicalized.as_str())
+    );
+    let mut mac = Hmac::<Sha1>::new_from_slice(format!("{access_key_secret}&").as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(string_to_sign.as_bytes());
+    BASE64_STANDARD.encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(endpoint: String) -> Aliyun {
+        Aliyun::from(Args::parse_from([
+            "aliyun",
+            "--domain",
+            "example.com",
+            "--rr",
+            "home",
+            "--access-key-id",
+            "testid",
+            "--access-key-secret",
+            "testsecret",
+            "--endpoint",
+            endpoint.as_str(),
+        ]))
+    }
+
+    #[test]
+    fn test_sign() {
+        // 阿里云文档中的签名示例
+        let params = BTreeMap::from(
+            [
+                ("Action", "DescribeDomainRecords"),
+                ("DomainName", "example.com"),
+                ("Format", "XML"),
+                ("AccessKeyId", "testid"),
+                ("SignatureMethod", "HMAC-SHA1"),
+                ("SignatureNonce", "f59ed6a9-83fc-473b-9cc6-99c95df3856e"),
+                ("SignatureVersion", "1.0"),
+                ("Timestamp", "2016-03-24T16:41:54Z"),
+                ("Version", "2015-01-09"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string())),
+        );
+        assert_eq!(
+            sign("testsecret", "GET", &params),
+            "uRpHwaSEt3J+6KQD//svCh/x+pI="
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_existing_record() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("Action", "DescribeSubDomainRecords"))
+            .and(query_param("SubDomain", "home.example.com"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "TotalCount": 1,
+                "DomainRecords": {
+                    "Record": [{"RecordId": "100", "RR": "home", "Type": "AAAA", "Value": "2001:db8::1"}]
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(query_param("Action", "UpdateDomainRecord"))
+            .and(query_param("RecordId", "100"))
+            .and(query_param("Value", "2001:db8::2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"RecordId": "100"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        client
+            .update_ip("2001:db8::2".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(client.record_ipv6, "2001:db8::2");
+    }
+
+    #[tokio::test]
+    async fn test_add_record() {
+        let server = MockServer::start().await;
+        Mock::given(query_param("Action", "DescribeSubDomainRecords"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "TotalCount": 0,
+                "DomainRecords": {"Record": []}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(query_param("Action", "AddDomainRecord"))
+            .and(query_param("DomainName", "example.com"))
+            .and(query_param("RR", "home"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"RecordId": "101"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        client
+            .update_ip("2001:db8::2".parse().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_access_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "Code": "InvalidAccessKeyId.NotFound",
+                "Message": "Specified access key is not found."
+            })))
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        let err = client
+            .update_ip("2001:db8::2".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DdnsError::Auth(_)));
+    }
+}
@@ -0,0 +1,478 @@
+use crate::error::{DdnsError, Result};
+use crate::verify::VerifyTarget;
+use crate::{DdnsClient, IpAddressType, RunArgs};
+use async_trait::async_trait;
+use clap::Parser;
+use common::time::timestamp_s;
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Client, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::net::Ipv6Addr;
+use tracing::{debug, info};
+
+/// 腾讯云api服务名
+const SERVICE: &str = "dnspod";
+/// 腾讯云api版本
+const API_VERSION: &str = "2021-03-23";
+const JSON_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+/// 默认线路
+const DEFAULT_LINE: &str = "默认";
+
+/// Update DNSPod (Tencent Cloud) ipv6 address
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Your domain name, e.g. `example.com`.
+    #[arg(short, long)]
+    domain: String,
+    /// Sub domain, `@` for the domain itself.
+    #[arg(long, default_value = "@")]
+    sub_domain: String,
+    /// Tencent Cloud SecretId.
+    #[arg(long)]
+    secret_id: String,
+    /// Tencent Cloud SecretKey.
+    #[arg(long)]
+    secret_key: String,
+    /// Tencent Cloud api endpoint
+    #[arg(long, default_value = "https://dnspod.tencentcloudapi.com")]
+    endpoint: String,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Clone)]
+pub struct Dnspod {
+    /// Your domain name
+    domain: String,
+    /// Sub domain
+    sub_domain: String,
+    secret_id: String,
+    secret_key: String,
+    /// Tencent Cloud api endpoint
+    endpoint: String,
+    /// Update interval and address watching
+    run: RunArgs,
+    client: Client,
+    /// ipv6 update before
+    record_ipv6: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiError {
+    code: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DescribeRecordList {
+    record_list: Vec<RecordListItem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RecordListItem {
+    record_id: u64,
+    value: String,
+    line: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RecordResponse {
+    record_id: u64,
+}
+
+impl From<Args> for Dnspod {
+    fn from(value: Args) -> Self {
+        Dnspod {
+            domain: value.domain,
+            sub_domain: value.sub_domain,
+            secret_id: value.secret_id,
+            secret_key: value.secret_key,
+            endpoint: value.endpoint,
+            run: value.run,
+            client: Client::new(),
+            record_ipv6: String::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl DdnsClient for Dnspod {
+    fn provider_name(&self) -> &'static str {
+        "dnspod"
+    }
+
+    fn support_type(&self) -> Vec<IpAddressType> {
+        vec![IpAddressType::Ipv6]
+    }
+
+    fn run_args(&self) -> &RunArgs {
+        &self.run
+    }
+
+    fn record_name(&self) -> String {
+        if self.sub_domain == "@" {
+            self.domain.clone()
+        } else {
+            format!("{}.{}", self.sub_domain, self.domain)
+        }
+    }
+
+    fn record_ip(&self) -> &str {
+        &self.record_ipv6
+    }
+
+    fn forget_record(&mut self) {
+        self.record_ipv6.clear();
+    }
+
+    fn verify_target(&self) -> VerifyTarget {
+        VerifyTarget {
+            zone: self.domain.clone(),
+            name: self.record_name(),
+        }
+    }
+
+    fn host_client(&self, host: &str) -> Option<Box<dyn DdnsClient + Send>> {
+        Some(Box::new(Dnspod {
+            sub_domain: host.to_string(),
+            record_ipv6: String::new(),
+            ..self.clone()
+        }))
+    }
+
+    fn info_log(&self) {
+        info!(domain = %self.domain, sub_domain = %self.sub_domain, interval = self.run.interval, "dnspod client started");
+    }
+
+    fn describe_update(&self, ip: Ipv6Addr) -> String {
+        format!(
+            "{}: DescribeRecordList Domain={} Subdomain={} RecordType=AAAA, then ModifyRecord or CreateRecord Value={}",
+            self.endpoint, self.domain, self.sub_domain, ip
+        )
+    }
+
+    async fn update_ip(&mut self, address: Ipv6Addr) -> Result<()> {
+        let ipv6 = address.to_string();
+        let ipv6 = ipv6.as_str();
+        if ipv6 == self.record_ipv6 {
+            debug!(domain = %self.domain, sub_domain = %self.sub_domain, ipv6, "address unchanged");
+            return Ok(());
+        }
+        let res = self
+            .request::<DescribeRecordList>(
+                "DescribeRecordList",
+                json!({
+                    "Domain": self.domain,
+                    "Subdomain": self.sub_domain,
+                    "RecordType": "AAAA",
+                }),
+            )
+            .await;
+        let record = match res {
+            Ok(records) => records.record_list.into_iter().next(),
+            // 记录不存在时返回错误码而不是空列表
+            Err(DdnsError::Provider { message, .. })
+                if message.starts_with("ResourceNotFound.NoDataOfRecord") =>
+            {
+                None
+            }
+            Err(err) => return Err(err),
+        };
+        match record {
+            Some(record) if record.value == ipv6 => {
+                debug!(domain = %self.domain, sub_domain = %self.sub_domain, ipv6, "record already up to date");
+            }
+            Some(record) => {
+                self.request::<RecordResponse>(
+                    "ModifyRecord",
+                    json!({
+                        "Domain": self.domain,
+                        "SubDomain": self.sub_domain,
+                        "RecordType": "AAAA",
+                        "RecordLine": record.line,
+                        "Value": ipv6,
+                        "RecordId": record.record_id,
+                    }),
+                )
+                .await?;
+                info!(domain = %self.domain, sub_domain = %self.sub_domain, ipv6, "record updated");
+            }
+            None => {
+                let res = self
+                    .request::<RecordResponse>(
+                        "CreateRecord",
+                        json!({
+                            "Domain": self.domain,
+                            "SubDomain": self.sub_domain,
+                            "RecordType": "AAAA",
+                            "RecordLine": DEFAULT_LINE,
+                            "Value": ipv6,
+                        }),
+                    )
+                    .await?;
+                info!(domain = %self.domain, sub_domain = %self.sub_domain, ipv6, record_id = res.record_id, "record added");
+            }
+        }
+        self.record_ipv6 = ipv6.to_string();
+        Ok(())
+    }
+}
+
+impl Dnspod {
+    /// 调用腾讯云api 3.0接口
+    async fn request<T: DeserializeOwned>(&self, action: &str, payload: Value) -> Result<T> {
+        let url = Url::parse(&self.endpoint).map_err(anyhow::Error::from)?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            _ => return Err(anyhow::anyhow!("invalid endpoint: {}", self.endpoint).into()),
+        };
+        let body = payload.to_string();
+        let timestamp = timestamp_s();
+        let authorization = tc3_authorization(
+            self.secret_id.as_str(),
+            self.secret_key.as_str(),
+            host.as_str(),
+            body.as_str(),
+            timestamp,
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&authorization).map_err(anyhow::Error::from)?,
+        );
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE));
+        headers.insert(
+            HeaderName::from_static("x-tc-action"),
+            HeaderValue::from_str(action).map_err(anyhow::Error::from)?,
+        );
+        headers.insert(
+            HeaderName::from_static("x-tc-timestamp"),
+            HeaderValue::from(timestamp),
+        );
+        headers.insert(
+            HeaderName::from_static("x-tc-version"),
+            HeaderValue::from_static(API_VERSION),
+        );
+        let response = self
+            .client
+            .post(url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+        let response = DdnsError::check_response(response).await?;
+        let mut value = response.json::<Value>().await?;
+        let mut response = value["Response"].take();
+        if let Some(err) = response.get_mut("Error") {
+            let err =
+                serde_json::from_value::<ApiError>(err.take()).map_err(anyhow::Error::from)?;
+            return Err(map_error(err));
+        }
+        Ok(serde_json::from_value(response).map_err(anyhow::Error::from)?)
+    }
+}
+
+/// 按错误码区分错误类型
+fn map_error(err: ApiError) -> DdnsError {
+    let code = err.code.as_str();
+    if code.starts_with("AuthFailure") || code.starts_with("UnauthorizedOperation") {
+        DdnsError::Auth(format!("{}: {}", err.code, err.message))
+    } else if code.starts_with("RequestLimitExceeded") {
+        DdnsError::RateLimited { retry_after: None }
+    } else {
+        DdnsError::Provider {
+            status: 200,
+            message: format!("{}: {}", err.code, err.message),
+        }
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// TC3-HMAC-SHA256签名，只签名content-type和host两个头
+fn tc3_authorization(
+    secret_id: &str,
+    secret_key: &str,
+    host: &str,
+    payload: &str,
+    timestamp: u64,
+) -> String {
+    let date = chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .expect("timestamp out of range")
+        .format("%Y-%m-%d")
+        .to_string();
+    let signed_headers = "content-type;host";
+    let canonical_request = format!(
+        "POST\n/\n\ncontent-type:{}\nhost:{}\n\n{}\n{}",
+        JSON_CONTENT_TYPE,
+        host,
+        signed_headers,
+        hex::encode(Sha256::digest(payload.as_bytes()))
+    );
+    let credential_scope = format!("{date}/{SERVICE}/tc3_request");
+    let string_to_sign = format!(
+        "TC3-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        credential_scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let secret_date = hmac_sha256(format!("TC3{secret_key}").as_bytes(), date.as_str());
+    let secret_service = hmac_sha256(&secret_date, SERVICE);
+    let secret_signing = hmac_sha256(&secret_service, "tc3_request");
+    let signature = hex::encode(hmac_sha256(&secret_signing, string_to_sign.as_str()));
+    format!(
+        "TC3-HMAC-SHA256 Credential={secret_id}/{credential_scope}, SignedHeaders={signed_headers}, Signature={signature}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, header, header_exists, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(endpoint: String) -> Dnspod {
+        Dnspod::from(Args::parse_from([
+            "dnspod",
+            "--domain",
+            "example.com",
+            "--sub-domain",
+            "home",
+            "--secret-id",
+            "AKIDtest",
+            "--secret-key",
+            "secret",
+            "--endpoint",
+            endpoint.as_str(),
+        ]))
+    }
+
+    #[test]
+    fn test_tc3_authorization() {
+        // 已知答案：按腾讯云TC3-HMAC-SHA256签名文档的步骤用独立实现计算
+        assert_eq!(
+            tc3_authorization(
+                "AKIDtest",
+                "secret",
+                "dnspod.tencentcloudapi.com",
+                "{}",
+                1700000000,
+            ),
+            "TC3-HMAC-SHA256 Credential=AKIDtest/2023-11-14/dnspod/tc3_request, SignedHeaders=content-type;host, Signature=7779fc7647517c0ce73fc734244e96c7c3fc82564130fb5c50706fbe65a1fb7b"
+        );
+        assert_ne!(
+            tc3_authorization(
+                "AKIDtest",
+                "secret",
+                "dnspod.tencentcloudapi.com",
+                "{ }",
+                1700000000
+            ),
+            tc3_authorization(
+                "AKIDtest",
+                "secret",
+                "dnspod.tencentcloudapi.com",
+                "{}",
+                1700000000
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_record() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-tc-action", "DescribeRecordList"))
+            .and(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Response": {
+                    "Error": {"Code": "ResourceNotFound.NoDataOfRecord", "Message": "记录列表为空。"},
+                    "RequestId": "1"
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(header("x-tc-action", "CreateRecord"))
+            .and(body_partial_json(
+                json!({"SubDomain": "home", "Value": "2001:db8::1"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Response": {"RecordId": 10, "RequestId": "2"}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        client
+            .update_ip("2001:db8::1".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(client.record_ipv6, "2001:db8::1");
+    }
+
+    #[tokio::test]
+    async fn test_modify_record() {
+        let server = MockServer::start().await;
+        Mock::given(header("x-tc-action", "DescribeRecordList"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Response": {
+                    "RecordList": [{"RecordId": 10, "Name": "home", "Type": "AAAA", "Line": "默认", "Value": "2001:db8::1"}],
+                    "RequestId": "1"
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(header("x-tc-action", "ModifyRecord"))
+            .and(body_partial_json(
+                json!({"RecordId": 10, "Value": "2001:db8::2"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Response": {"RecordId": 10, "RequestId": "2"}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        client
+            .update_ip("2001:db8::2".parse().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auth_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Response": {
+                    "Error": {"Code": "AuthFailure.SignatureFailure", "Message": "signature failure"},
+                    "RequestId": "1"
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        let err = client
+            .update_ip("2001:db8::2".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DdnsError::Auth(_)));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::error::{DdnsError, Result};
+use crate::verify::VerifyTarget;
+use crate::{DdnsClient, IpAddressType, RunArgs};
+use async_trait::async_trait;
+use clap::Parser;
+use std::net::Ipv6Addr;
+use tracing::{debug, info};
+
+/// Update DuckDNS ipv6 address
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Your DuckDNS sub domains, comma separated, without `.duckdns.org`.
+    #[arg(short, long)]
+    domains: String,
+    /// Your DuckDNS account token.
+    #[arg(short, long)]
+    token: String,
+    /// DuckDNS api endpoint
+    #[arg(long, default_value = "https://www.duckdns.org")]
+    endpoint: String,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Clone)]
+pub struct DuckDns {
+    /// Your DuckDNS sub domains
+    domains: String,
+    /// Your DuckDNS account token
+    token: String,
+    /// DuckDNS api endpoint
+    endpoint: String,
+    /// Update interval and address watching
+    run: RunArgs,
+    /// ipv6 update before
+    record_ipv6: String,
+}
+
+impl From<Args> for DuckDns {
+    fn from(value: Args) -> Self {
+        DuckDns {
+            domains: value.domains,
+            token: value.token,
+            endpoint: value.endpoint,
+            run: value.run,
+            record_ipv6: String::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl DdnsClient for DuckDns {
+    fn provider_name(&self) -> &'static str {
+        "duckdns"
+    }
+
+    fn support_type(&self) -> Vec<IpAddressType> {
+        vec![IpAddressType::Ipv6]
+    }
+
+    fn run_args(&self) -> &RunArgs {
+        &self.run
+    }
+
+    fn record_name(&self) -> String {
+        self.domains.clone()
+    }
+
+    fn record_ip(&self) -> &str {
+        &self.record_ipv6
+    }
+
+    fn forget_record(&mut self) {
+        self.record_ipv6.clear();
+    }
+
+    fn verify_target(&self) -> VerifyTarget {
+        // 多个域名时只校验第一个
+        let domain = self.domains.split(',').next().unwrap_or_default().trim();
+        VerifyTarget {
+            zone: "duckdns.org".to_string(),
+            name: format!("{domain}.duckdns.org"),
+        }
+    }
+
+    fn host_client(&self, host: &str) -> Option<Box<dyn DdnsClient + Send>> {
+        Some(Box::new(DuckDns {
+            domains: host.to_string(),
+            record_ipv6: String::new(),
+            ..self.clone()
+        }))
+    }
+
+    fn info_log(&self) {
+        info!(domains = %self.domains, interval = self.run.interval, "duckdns client started");
+    }
+
+    fn describe_update(&self, ip: Ipv6Addr) -> String {
+        format!(
+            "GET {}/update?domains={}&token=***&ipv6={}",
+            self.endpoint, self.domains, ip
+        )
+    }
+
+    async fn update_ip(&mut self, address: Ipv6Addr) -> Result<()> {
+        let ipv6 = address.to_string();
+        let ipv6 = ipv6.as_str();
+        if ipv6 == self.record_ipv6 {
+            debug!(domains = %self.domains, ipv6, "address unchanged");
+            return Ok(());
+        }
+        let response = reqwest::Client::new()
+            .get(format!("{}/update", self.endpoint))
+            .query(&[
+                ("domains", self.domains.as_str()),
+                ("token", self.token.as_str()),
+                ("ipv6", ipv6),
+            ])
+            .send()
+            .await?;
+        let body = DdnsError::check_response(response).await?.text().await?;
+        // duckdns只返回OK或KO，token或域名错误时都是KO
+        if body.trim() != "OK" {
+            return Err(DdnsError::Auth(body));
+        }
+        info!(domains = %self.domains, ipv6, "record updated");
+        self.record_ipv6 = ipv6.to_string();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(endpoint: String) -> DuckDns {
+        DuckDns::from(Args::parse_from([
+            "duckdns",
+            "--domains",
+            "home",
+            "--token",
+            "secret",
+            "--endpoint",
+            endpoint.as_str(),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn test_update_ip() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/update"))
+            .and(query_param("domains", "home"))
+            .and(query_param("token", "secret"))
+            .and(query_param("ipv6", "2001:db8::1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        client
+            .update_ip("2001:db8::1".parse().unwrap())
+            .await
+            .unwrap();
+        // 地址未变化时不会重复请求
+        client
+            .update_ip("2001:db8::1".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(client.record_ipv6, "2001:db8::1");
+    }
+
+    #[tokio::test]
+    async fn test_update_ip_ko() {
+        let server = MockServer::start().await;
+        Mock::given(path("/update"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("KO"))
+            .mount(&server)
+            .await;
+
+        let mut client = client(server.uri());
+        let err = client
+            .update_ip("2001:db8::1".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DdnsError::Auth(_)));
+        assert!(client.record_ipv6.is_empty());
+    }
+}
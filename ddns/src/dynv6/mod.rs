@@ -1,7 +1,10 @@
-use crate::{get_ipv6_list, DdnsClient, IpAddressType};
+use crate::error::{DdnsError, Result};
+use crate::verify::VerifyTarget;
+use crate::{DdnsClient, IpAddressType, RunArgs};
 use async_trait::async_trait;
 use clap::Parser;
-use reqwest::StatusCode;
+use std::net::Ipv6Addr;
+use tracing::{debug, info};
 
 /// Simple program to update dynv6 ipv6 address!
 #[derive(Parser, Debug)]
@@ -13,9 +16,11 @@ pub struct Args {
     /// An HTTP token for this zone.
     #[arg(short, long)]
     token: String,
-    #[arg(short, long, default_value_t = 60)]
-    /// Update interval second
-    interval: u64,
+    /// dynv6 api endpoint
+    #[arg(long, default_value = "https://dynv6.com")]
+    endpoint: String,
+    #[command(flatten)]
+    run: RunArgs,
 }
 
 pub struct Dynv6 {
@@ -23,8 +28,10 @@ pub struct Dynv6 {
     zone: String,
     /// An HTTP token for this zone.
     token: String,
-    /// Update interval secs
-    interval: u64,
+    /// dynv6 api endpoint
+    endpoint: String,
+    /// Update interval and address watching
+    run: RunArgs,
     /// ipv6 update before
     record_ipv6: String,
 }
@@ -34,7 +41,8 @@ impl From<Args> for Dynv6 {
         Dynv6 {
             zone: value.zone,
             token: value.token,
-            interval: value.interval,
+            endpoint: value.endpoint,
+            run: value.run,
             record_ipv6: String::new(),
         }
     }
@@ -42,41 +50,134 @@ impl From<Args> for Dynv6 {
 
 #[async_trait]
 impl DdnsClient for Dynv6 {
+    fn provider_name(&self) -> &'static str {
+        "dynv6"
+    }
+
     fn support_type(&self) -> Vec<IpAddressType> {
         vec![IpAddressType::Ipv6]
     }
 
-    fn interval_secs(&self) -> u64 {
-        self.interval
+    fn run_args(&self) -> &RunArgs {
+        &self.run
+    }
+
+    fn record_name(&self) -> String {
+        self.zone.clone()
+    }
+
+    fn record_ip(&self) -> &str {
+        &self.record_ipv6
+    }
+
+    fn forget_record(&mut self) {
+        self.record_ipv6.clear();
+    }
+
+    fn verify_target(&self) -> VerifyTarget {
+        VerifyTarget {
+            zone: self.zone.clone(),
+            name: self.zone.clone(),
+        }
+    }
+
+    fn info_log(&self) {
+        info!(zone = %self.zone, interval = self.run.interval, "dynv6 client started");
+    }
+
+    fn describe_update(&self, ip: Ipv6Addr) -> String {
+        format!(
+            "GET {}/api/update?zone={}&token=***&ipv6={}",
+            self.endpoint, self.zone, ip
+        )
     }
 
-    async fn update(&mut self) -> anyhow::Result<()> {
-        let res = get_ipv6_list()?;
-        if res.len() > 0 {
-            let ipv6 = res[0].as_str();
-            self.update_ip(ipv6).await?;
+    async fn update_ip(&mut self, address: Ipv6Addr) -> Result<()> {
+        let ipv6 = address.to_string();
+        let ipv6 = ipv6.as_str();
+        if ipv6 == self.record_ipv6 {
+            debug!(zone = %self.zone, ipv6, "address unchanged");
+            return Ok(());
         }
+        let response = reqwest::Client::new()
+            .get(format!("{}/api/update", self.endpoint))
+            .query(&[
+                ("zone", self.zone.as_str()),
+                ("token", self.token.as_str()),
+                ("ipv6", ipv6),
+            ])
+            .send()
+            .await?;
+        DdnsError::check_response(response).await?;
+        info!(zone = %self.zone, ipv6, "record updated");
+        self.record_ipv6 = ipv6.to_string();
         Ok(())
     }
 }
 
-impl Dynv6 {
-    pub fn info_log(&self) {
-        println!("zone: {} token: {}", self.zone, self.token);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Source;
+    use crate::testing::ScriptedSource;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(endpoint: String, source: ScriptedSource) -> Dynv6 {
+        let mut client = Dynv6::from(Args::parse_from([
+            "dynv6",
+            "--zone",
+            "home.dynv6.net",
+            "--token",
+            "secret",
+            "--endpoint",
+            endpoint.as_str(),
+        ]));
+        client.run.source = Source::new(source);
+        client
     }
 
-    async fn update_ip(&mut self, ipv6: &str) -> anyhow::Result<()> {
-        if ipv6 != self.record_ipv6 {
-            // zone: &str, token: &str, ipv6: &str
-            let url = format!(
-                "https://dynv6.com/api/update?zone={}&token={}&ipv6={}",
-                self.zone, self.token, ipv6
-            );
-            let response = reqwest::get(url).await?;
-            if response.status() == StatusCode::OK {
-                self.record_ipv6 = ipv6.to_string();
-            }
+    #[tokio::test]
+    async fn test_run_once() {
+        let server = MockServer::start().await;
+        for ipv6 in ["2001:db8::1", "2001:db8::2"] {
+            Mock::given(method("GET"))
+                .and(path("/api/update"))
+                .and(query_param("zone", "home.dynv6.net"))
+                .and(query_param("token", "secret"))
+                .and(query_param("ipv6", ipv6))
+                .respond_with(ResponseTemplate::new(200).set_body_string("addresses updated"))
+                .expect(1)
+                .mount(&server)
+                .await;
         }
-        Ok(())
+
+        let source = ScriptedSource::new(vec![
+            Ok(vec!["fe80::1/64", "2001:db8::1/64"]),
+            Ok(vec!["2001:db8::1/64"]),
+            Ok(vec!["2001:db8::2/64"]),
+        ]);
+        let mut client = client(server.uri(), source);
+        for _ in 0..3 {
+            client.run_once().await.unwrap();
+        }
+        assert_eq!(client.record_ipv6, "2001:db8::2");
+    }
+
+    #[tokio::test]
+    async fn test_run_once_unauthorized() {
+        let server = MockServer::start().await;
+        Mock::given(path("/api/update"))
+            .respond_with(
+                ResponseTemplate::new(401).set_body_string("invalid authentication token"),
+            )
+            .mount(&server)
+            .await;
+
+        let source = ScriptedSource::fixed(vec!["2001:db8::1/64"]);
+        let mut client = client(server.uri(), source);
+        let err = client.run_once().await.unwrap_err();
+        assert!(matches!(err, DdnsError::Auth(_)));
+        assert!(client.record_ipv6.is_empty());
     }
 }
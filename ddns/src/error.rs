@@ -0,0 +1,175 @@
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use std::net::Ipv6Addr;
+use std::time::Duration;
+
+/// ddns更新错误
+#[derive(Debug, thiserror::Error)]
+pub enum DdnsError {
+    /// 鉴权失败，token错误或权限不足
+    #[error("authentication failed: {0}")]
+    Auth(String),
+    /// 请求过于频繁
+    #[error("rate limited by provider")]
+    RateLimited {
+        /// 服务端要求的等待时间
+        retry_after: Option<Duration>,
+    },
+    /// 网络错误
+    #[error("network error: {0}")]
+    Network(reqwest::Error),
+    /// 服务商返回的其他错误
+    #[error("provider error: {status} {message}")]
+    Provider { status: u16, message: String },
+    /// 没有检测到可发布的地址
+    #[error("no address detected")]
+    NoAddress,
+    /// 更新后记录在超时时间内没有解析到发布的地址
+    #[error("record {name} not propagated, expected {expected}, found {found:?}")]
+    NotPropagated {
+        name: String,
+        expected: Ipv6Addr,
+        found: Vec<Ipv6Addr>,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, DdnsError>;
+
+impl From<reqwest::Error> for DdnsError {
+    fn from(err: reqwest::Error) -> Self {
+        // url中可能带有token，不能出现在日志中
+        DdnsError::Network(err.without_url())
+    }
+}
+
+impl DdnsError {
+    /// 错误类型名称，用于监控指标
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DdnsError::Auth(_) => "auth",
+            DdnsError::RateLimited { .. } => "rate_limited",
+            DdnsError::Network(_) => "network",
+            DdnsError::Provider { .. } => "provider",
+            DdnsError::NoAddress => "no_address",
+            DdnsError::NotPropagated { .. } => "not_propagated",
+            DdnsError::Other(_) => "other",
+        }
+    }
+
+    /// --once模式下的进程退出码
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            DdnsError::Other(_) => 1,
+            DdnsError::Auth(_) => 2,
+            DdnsError::RateLimited { .. } => 3,
+            DdnsError::Network(_) => 4,
+            DdnsError::Provider { .. } => 5,
+            DdnsError::NoAddress => 6,
+            DdnsError::NotPropagated { .. } => 7,
+        }
+    }
+
+    /// 检查http响应状态码，失败时按状态码区分错误类型
+    pub async fn check_response(response: Response) -> Result<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let message = response.text().await.unwrap_or_default();
+        Err(Self::from_status(status, message, retry_after))
+    }
+
+    pub fn from_status(status: StatusCode, message: String, retry_after: Option<Duration>) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => DdnsError::Auth(message),
+            StatusCode::TOO_MANY_REQUESTS => DdnsError::RateLimited { retry_after },
+            _ => DdnsError::Provider {
+                status: status.as_u16(),
+                message,
+            },
+        }
+    }
+}
+
+/// 更新失败后的指数退避
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            attempt: 0,
+        }
+    }
+
+    /// 下一次重试的等待时间，每次失败翻倍，不超过max
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .initial
+            .checked_mul(2u32.saturating_pow(self.attempt))
+            .unwrap_or(self.max)
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        delay
+    }
+
+    /// 更新成功后重置
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// 当前连续失败次数
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(30));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(10));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(20));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(30));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(30));
+        assert_eq!(backoff.attempt(), 5);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_from_status() {
+        let err = DdnsError::from_status(StatusCode::UNAUTHORIZED, "invalid token".into(), None);
+        assert!(matches!(err, DdnsError::Auth(_)));
+        let err = DdnsError::from_status(
+            StatusCode::TOO_MANY_REQUESTS,
+            String::new(),
+            Some(Duration::from_secs(60)),
+        );
+        assert!(matches!(
+            err,
+            DdnsError::RateLimited {
+                retry_after: Some(_)
+            }
+        ));
+        let err = DdnsError::from_status(StatusCode::BAD_REQUEST, "bad".into(), None);
+        assert!(matches!(err, DdnsError::Provider { status: 400, .. }));
+    }
+}
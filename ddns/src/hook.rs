@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time;
+use tracing::{info, warn};
+
+/// 单个通知的超时时间
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 地址变更通知参数
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct HookArgs {
+    /// Shell command to run when the published address changes,
+    /// with `DDNS_NAME`, `DDNS_OLD_IP` and `DDNS_NEW_IP` set
+    #[arg(long)]
+    pub on_change: Option<String>,
+    /// Webhook url to POST a json message to when the published address changes
+    #[arg(long)]
+    pub webhook: Option<String>,
+    /// Feishu bot webhook url to send a text message to when the published address changes
+    #[arg(long)]
+    pub feishu_webhook: Option<String>,
+}
+
+/// 发布地址变更
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressChange {
+    /// 记录名称
+    pub name: String,
+    /// 变更前的地址，启动后首次发布时为空
+    pub old: String,
+    /// 新发布的地址
+    pub new: String,
+}
+
+impl HookArgs {
+    /// 并发执行所有通知，失败只记录日志，不影响dns更新
+    pub async fn notify(&self, change: AddressChange) {
+        let command = async {
+            if let Some(command) = &self.on_change {
+                log_result("command", run_command(command, &change).await);
+            }
+        };
+        let webhook = async {
+            if let Some(url) = &self.webhook {
+                log_result("webhook", post_webhook(url, &change).await);
+            }
+        };
+        let feishu = async {
+            if let Some(url) = &self.feishu_webhook {
+                log_result("feishu", post_feishu(url, &change).await);
+            }
+        };
+        tokio::join!(command, webhook, feishu);
+    }
+}
+
+fn log_result(hook: &str, res: Result<()>) {
+    match res {
+        Ok(()) => info!(hook, "address change notified"),
+        Err(err) => warn!(hook, error = %err, "address change notify failed"),
+    }
+}
+
+async fn run_command(command: &str, change: &AddressChange) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    };
+    #[cfg(not(target_os = "windows"))]
+    let mut cmd = {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    cmd.env("DDNS_NAME", &change.name)
+        .env("DDNS_OLD_IP", &change.old)
+        .env("DDNS_NEW_IP", &change.new)
+        .kill_on_drop(true);
+    let status = time::timeout(HOOK_TIMEOUT, cmd.status()).await??;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("command exited with {}", status))
+    }
+}
+
+async fn post_webhook(url: &str, change: &AddressChange) -> Result<()> {
+    Client::new()
+        .post(url)
+        .timeout(HOOK_TIMEOUT)
+        .json(change)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        // webhook地址中通常带有密钥
+        .map_err(reqwest::Error::without_url)?;
+    Ok(())
+}
+
+async fn post_feishu(url: &str, change: &AddressChange) -> Result<()> {
+    common::feishu::send_text(url, &feishu_text(change)).await
+}
+
+fn feishu_text(change: &AddressChange) -> String {
+    if change.old.is_empty() {
+        format!("[ddns] {} published {}", change.name, change.new)
+    } else {
+        format!(
+            "[ddns] {} changed from {} to {}",
+            change.name, change.old, change.new
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{body_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn change() -> AddressChange {
+        AddressChange {
+            name: "home.example.com".to_string(),
+            old: "2001:db8::1".to_string(),
+            new: "2001:db8::2".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_webhook() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_json(json!({
+                "name": "home.example.com",
+                "old": "2001:db8::1",
+                "new": "2001:db8::2",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        post_webhook(server.uri().as_str(), &change())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_feishu_text() {
+        assert_eq!(
+            feishu_text(&change()),
+            "[ddns] home.example.com changed from 2001:db8::1 to 2001:db8::2"
+        );
+        let first = AddressChange {
+            old: String::new(),
+            ..change()
+        };
+        assert_eq!(
+            feishu_text(&first),
+            "[ddns] home.example.com published 2001:db8::2"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command() {
+        run_command(r#"test "$DDNS_NEW_IP" = "2001:db8::2""#, &change())
+            .await
+            .unwrap();
+        assert!(run_command("exit 1", &change()).await.is_err());
+    }
+}
@@ -3,9 +3,38 @@ use async_trait::async_trait;
 use std::process::Command;
 use std::time::Duration;
 use tokio::time;
+use tracing::{error, info, warn};
 
+pub mod aliyun;
 pub mod cloudflare;
+pub mod dnspod;
+pub mod duckdns;
 pub mod dynv6;
+pub mod error;
+pub mod hook;
+pub mod metrics;
+pub mod prefix;
+pub mod rfc2136;
+pub mod source;
+/// 测试用的地址来源和服务商
+#[cfg(test)]
+mod testing;
+pub mod verify;
+pub mod watch;
+
+use error::{Backoff, DdnsError};
+use hook::{AddressChange, HookArgs};
+use metrics::Metrics;
+use prefix::PrefixArgs;
+use source::{parse_address, select_ip, Source};
+use std::net::{Ipv6Addr, SocketAddr};
+use verify::{VerifyArgs, VerifyTarget};
+use watch::AddressWatcher;
+
+/// 更新失败后首次重试的等待时间
+const RETRY_INITIAL: Duration = Duration::from_secs(5);
+/// 更新失败后重试的最大等待时间
+const RETRY_MAX: Duration = Duration::from_secs(30 * 60);
 
 /// ip地址类型
 pub enum IpAddressType {
@@ -13,22 +42,223 @@ pub enum IpAddressType {
     Ipv6,
 }
 
+/// 各服务商通用的运行参数
+#[derive(clap::Args, Debug, Clone)]
+pub struct RunArgs {
+    /// Update interval second
+    #[arg(short, long, default_value_t = 60)]
+    pub interval: u64,
+    /// Update as soon as the kernel reports an address change (linux only)
+    #[arg(short, long)]
+    pub watch: bool,
+    /// Debounce window for address change events, in milliseconds
+    #[arg(long, default_value_t = 2000)]
+    pub debounce: u64,
+    /// Run a single update and exit. Exit code: 0 success, 1 other error,
+    /// 2 authentication failed, 3 rate limited, 4 network error, 5 provider error, 6 no address,
+    /// 7 record not propagated
+    #[arg(long)]
+    pub once: bool,
+    /// Print detected addresses and the update that would be sent, without calling the provider
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Serve `/healthz` and prometheus `/metrics` on this address, e.g. `127.0.0.1:9090`
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+    #[command(flatten)]
+    pub hooks: HookArgs,
+    #[command(flatten)]
+    pub verify: VerifyArgs,
+    #[command(flatten)]
+    pub prefix: PrefixArgs,
+    /// 本机地址来源
+    #[arg(skip)]
+    pub source: Source,
+}
+
+impl RunArgs {
+    /// 未开启地址监听时返回None
+    pub fn watch_debounce(&self) -> Option<Duration> {
+        self.watch.then(|| Duration::from_millis(self.debounce))
+    }
+}
+
 #[async_trait]
 pub trait DdnsClient {
+    /// 服务商名称
+    fn provider_name(&self) -> &'static str;
     /// 支持的ddns类型
     fn support_type(&self) -> Vec<IpAddressType>;
+    /// 运行参数
+    fn run_args(&self) -> &RunArgs;
     /// 更新间隔：单位秒
-    fn interval_secs(&self) -> u64;
+    fn interval_secs(&self) -> u64 {
+        self.run_args().interval
+    }
+    /// 地址变更事件的防抖时间，返回None时只按固定间隔更新
+    fn watch_debounce(&self) -> Option<Duration> {
+        self.run_args().watch_debounce()
+    }
+    /// 记录名称，用于日志和通知
+    fn record_name(&self) -> String;
+    /// 最近一次成功发布的地址
+    fn record_ip(&self) -> &str;
+    /// 每条记录的名称和已发布的地址，用于监控指标和变更通知
+    fn records(&self) -> Vec<(String, String)> {
+        vec![(self.record_name(), self.record_ip().to_string())]
+    }
+    /// 清除已发布的地址，下次更新时重新发布
+    fn forget_record(&mut self);
+    /// 发布后需要校验的记录
+    fn verify_target(&self) -> VerifyTarget;
+    /// 发布同一zone下另一条记录的客户端，`host`为相对zone的名称，不支持时返回None
+    fn host_client(&self, _host: &str) -> Option<Box<dyn DdnsClient + Send>> {
+        None
+    }
+
+    /// 输出客户端配置信息，不能包含密钥
+    fn info_log(&self) {}
+    /// 描述发布该地址时将发送的请求，不能包含密钥
+    fn describe_update(&self, ip: Ipv6Addr) -> String;
+
+    /// 发布地址，地址未变化时不发送请求，地址已去掉`/前缀长度`
+    async fn update_ip(&mut self, ip: Ipv6Addr) -> error::Result<()>;
 
-    async fn update(&mut self) -> anyhow::Result<()>;
+    async fn update(&mut self) -> error::Result<()> {
+        let res = self.run_args().source.ipv6_list()?;
+        let ip = select_ip(&res).ok_or(DdnsError::NoAddress)?;
+        self.update_ip(ip).await
+    }
+
+    /// 只输出检测到的地址和将要发送的请求，不调用服务商接口
+    fn dry_run(&self) -> error::Result<()> {
+        let res = self.run_args().source.ipv6_list()?;
+        println!("detected addresses:");
+        for ip in &res {
+            println!("  {ip}");
+        }
+        let ip = select_ip(&res).ok_or(DdnsError::NoAddress)?;
+        println!("selected: {ip}");
+        println!("{}: {}", self.record_name(), self.describe_update(ip));
+        Ok(())
+    }
+
+    /// 校验新发布的地址是否生效，未生效时清除记录以便重新发布
+    async fn verify(&mut self) -> error::Result<()> {
+        let verify = &self.run_args().verify;
+        if !verify.enabled() {
+            return Ok(());
+        }
+        let expected = parse_address(self.record_ip())
+            .ok_or_else(|| anyhow!("invalid published address {}", self.record_ip()))?;
+        let res = verify
+            .wait_propagated(&self.verify_target(), expected)
+            .await;
+        if res.is_err() {
+            self.forget_record();
+        }
+        res
+    }
+
+    /// 地址发生变化时校验记录
+    async fn update_and_verify(&mut self, old: &str) -> error::Result<()> {
+        self.update().await?;
+        if self.record_ip() != old {
+            self.verify().await?;
+        }
+        Ok(())
+    }
+
+    /// 执行一次更新并等待通知完成，部分记录发布失败时已发布的记录仍会通知
+    async fn run_once(&mut self) -> error::Result<()> {
+        let old_records = self.records();
+        let old = self.record_ip().to_string();
+        let res = self.update_and_verify(&old).await;
+        let hooks = &self.run_args().hooks;
+        for change in self.address_changes(&old_records) {
+            hooks.notify(change).await;
+        }
+        res
+    }
+
+    /// 与`old`相比新发布的记录，每条记录单独通知
+    fn address_changes(&self, old: &[(String, String)]) -> Vec<AddressChange> {
+        self.records()
+            .into_iter()
+            .filter_map(|(name, new)| {
+                let old = old
+                    .iter()
+                    .find(|(old_name, _)| *old_name == name)
+                    .map(|(_, ip)| ip.clone())
+                    .unwrap_or_default();
+                (!new.is_empty() && new != old).then_some(AddressChange { name, old, new })
+            })
+            .collect()
+    }
 
     async fn run(&mut self) {
         let period = Duration::from_secs(self.interval_secs());
         let mut interval = time::interval(period);
+        let mut backoff = Backoff::new(RETRY_INITIAL, RETRY_MAX);
+        let metrics = Metrics::new(self.provider_name());
+        if let Some(addr) = self.run_args().metrics_addr {
+            tokio::spawn(metrics.clone().serve(addr));
+        }
+        // 监听地址变更时立即更新，固定间隔作为兜底
+        let mut watcher =
+            self.watch_debounce()
+                .and_then(|debounce| match AddressWatcher::try_new() {
+                    Ok(watcher) => Some((watcher, debounce)),
+                    Err(err) => {
+                        warn!(error = %err, "watch address failed, fallback to interval");
+                        None
+                    }
+                });
         loop {
-            interval.tick().await;
-            if let Err(err) = self.update().await {
-                println!("{}", err);
+            if let Some((address_watcher, debounce)) = watcher.as_mut() {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    res = address_watcher.changed(*debounce) => {
+                        match res {
+                            Ok(()) => info!("address changed"),
+                            Err(err) => {
+                                warn!(error = %err, "watch address failed, fallback to interval");
+                                watcher = None;
+                            }
+                        }
+                        interval.reset();
+                    }
+                }
+            } else {
+                interval.tick().await;
+            }
+            let old_records = self.records();
+            let old = self.record_ip().to_string();
+            let res = self.update_and_verify(&old).await;
+            metrics.record_update(&self.records(), res.as_ref().map(|_| ()));
+            for change in self.address_changes(&old_records) {
+                // 通知在后台执行，不阻塞下一次更新
+                let hooks = self.run_args().hooks.clone();
+                tokio::spawn(async move { hooks.notify(change).await });
+            }
+            match res {
+                Ok(()) => backoff.reset(),
+                Err(err) => {
+                    let mut delay = backoff.next_delay();
+                    if let DdnsError::RateLimited {
+                        retry_after: Some(retry_after),
+                    } = &err
+                    {
+                        delay = delay.max(*retry_after);
+                    }
+                    let attempt = backoff.attempt();
+                    if let DdnsError::Auth(_) = &err {
+                        error!(error = %err, attempt, retry_in = ?delay, "update failed");
+                    } else {
+                        warn!(error = %err, attempt, retry_in = ?delay, "update failed");
+                    }
+                    interval.reset_after(delay);
+                }
             }
         }
     }
@@ -107,3 +337,104 @@ pub fn get_ipv6_list() -> anyhow::Result<Vec<String>> {
         Err(anyhow!("Command execution error!"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{run_args, Call, MockProvider, ScriptedSource};
+
+    /// 在后台运行更新循环，返回`duration`内的全部发布请求
+    async fn run_for(mut client: MockProvider, duration: Duration) -> Vec<Call> {
+        let calls = client.calls();
+        let handle = tokio::spawn(async move { client.run().await });
+        time::sleep(duration).await;
+        handle.abort();
+        calls.lock().unwrap().clone()
+    }
+
+    /// 各次请求相对第一次请求的秒数
+    fn offsets(calls: &[Call]) -> Vec<u64> {
+        calls
+            .iter()
+            .map(|call| (call.at - calls[0].at).as_secs())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_run_once() {
+        let source = ScriptedSource::new(vec![
+            Ok(vec!["fe80::1/64", "2001:db8::1/64"]),
+            Ok(vec!["2001:db8::1/64"]),
+            Err("ip command failed"),
+            Ok(vec!["fe80::1/64"]),
+            Ok(vec!["2001:db8::2/64"]),
+        ]);
+        let mut client = MockProvider::new(run_args(&["--once"], source), vec![]);
+
+        client.run_once().await.unwrap();
+        assert_eq!(client.record_ip(), "2001:db8::1");
+        // 地址未变化时不发送请求
+        client.run_once().await.unwrap();
+        let err = client.run_once().await.unwrap_err();
+        assert!(matches!(err, DdnsError::Other(_)));
+        let err = client.run_once().await.unwrap_err();
+        assert!(matches!(err, DdnsError::NoAddress));
+        client.run_once().await.unwrap();
+
+        let calls = client.calls().lock().unwrap().clone();
+        let ips = calls
+            .iter()
+            .map(|call| call.ip.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ips, ["2001:db8::1", "2001:db8::2"]);
+        assert_eq!(client.record_ip(), "2001:db8::2");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_interval() {
+        let source = ScriptedSource::new(vec![
+            Ok(vec!["2001:db8::1/64"]),
+            Ok(vec!["2001:db8::1/64"]),
+            Ok(vec!["2001:db8::2/64"]),
+        ]);
+        let client = MockProvider::new(run_args(&["-i", "60"], source), vec![]);
+        let calls = run_for(client, Duration::from_secs(150)).await;
+        assert_eq!(offsets(&calls), [0, 120]);
+        assert_eq!(calls[1].ip, "2001:db8::2");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_retry_backoff() {
+        let source = ScriptedSource::fixed(vec!["2001:db8::1/64"]);
+        let failures = vec![
+            DdnsError::Provider {
+                status: 500,
+                message: "internal error".to_string(),
+            },
+            DdnsError::Provider {
+                status: 502,
+                message: "bad gateway".to_string(),
+            },
+            DdnsError::Provider {
+                status: 503,
+                message: "unavailable".to_string(),
+            },
+        ];
+        let client = MockProvider::new(run_args(&["-i", "600"], source), failures);
+        let calls = run_for(client, Duration::from_secs(100)).await;
+        // 失败后按5s、10s、20s重试，成功后恢复固定间隔
+        assert_eq!(offsets(&calls), [0, 5, 15, 35]);
+        assert!(calls[3].ok);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_retry_after() {
+        let source = ScriptedSource::fixed(vec!["2001:db8::1/64"]);
+        let failures = vec![DdnsError::RateLimited {
+            retry_after: Some(Duration::from_secs(90)),
+        }];
+        let client = MockProvider::new(run_args(&["-i", "600"], source), failures);
+        let calls = run_for(client, Duration::from_secs(100)).await;
+        assert_eq!(offsets(&calls), [0, 90]);
+    }
+}
@@ -1,4 +1,79 @@
+use clap::{Parser, Subcommand};
+use ddns::aliyun::Aliyun;
+use ddns::dnspod::Dnspod;
+use ddns::duckdns::DuckDns;
+use ddns::dynv6::Dynv6;
+use ddns::prefix::PrefixGroup;
+use ddns::rfc2136::Rfc2136;
+use ddns::{aliyun, dnspod, duckdns, dynv6, rfc2136, DdnsClient};
+use std::process::ExitCode;
+use tracing::error;
+use tracing_subscriber::EnvFilter;
+
+/// Dynamic DNS client
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    provider: Provider,
+}
+
+#[derive(Subcommand, Debug)]
+enum Provider {
+    Dynv6(dynv6::Args),
+    Duckdns(duckdns::Args),
+    Aliyun(aliyun::Args),
+    Dnspod(dnspod::Args),
+    Rfc2136(rfc2136::Args),
+}
+
 #[tokio::main]
-async fn main() {
-    println!("Hello, world!");
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
+    let mut client: Box<dyn DdnsClient + Send> = match Cli::parse().provider {
+        Provider::Dynv6(args) => Box::new(Dynv6::from(args)),
+        Provider::Duckdns(args) => Box::new(DuckDns::from(args)),
+        Provider::Aliyun(args) => Box::new(Aliyun::from(args)),
+        Provider::Dnspod(args) => Box::new(Dnspod::from(args)),
+        Provider::Rfc2136(args) => Box::new(Rfc2136::from(args)),
+    };
+    if !client.run_args().prefix.hosts.is_empty() {
+        client = match PrefixGroup::try_new(client) {
+            Ok(group) => Box::new(group),
+            Err(err) => {
+                error!(error = %err, "invalid arguments");
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    client.info_log();
+
+    let res = if client.run_args().dry_run {
+        client.dry_run()
+    } else if client.run_args().once {
+        client.run_once().await
+    } else {
+        client.run().await;
+        Ok(())
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!(error = %err, "update failed");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
 }
@@ -0,0 +1,202 @@
+use crate::error::DdnsError;
+use common::time::timestamp_s;
+use salvo::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info};
+
+/// 更新循环的运行状态，供健康检查和prometheus使用
+#[derive(Clone)]
+pub struct Metrics {
+    provider: &'static str,
+    state: Arc<Mutex<MetricsState>>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct MetricsState {
+    started_at: u64,
+    /// 最近一次执行更新的时间
+    last_update_at: Option<u64>,
+    /// 最近一次更新成功的时间
+    last_success_at: Option<u64>,
+    /// 记录名称 -> 当前发布的地址
+    records: BTreeMap<String, String>,
+    attempts: u64,
+    /// 失败原因 -> 次数
+    failures: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    pub fn new(provider: &'static str) -> Self {
+        Self {
+            provider,
+            state: Arc::new(Mutex::new(MetricsState {
+                started_at: timestamp_s(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// 记录一次更新结果，`records`为每条记录的名称和已发布的地址
+    pub fn record_update(&self, records: &[(String, String)], res: Result<(), &DdnsError>) {
+        let now = timestamp_s();
+        let mut state = self.state.lock().unwrap();
+        state.attempts += 1;
+        state.last_update_at = Some(now);
+        // 部分记录失败时已发布的记录也要更新
+        for (record, address) in records {
+            if !address.is_empty() {
+                state.records.insert(record.clone(), address.clone());
+            }
+        }
+        match res {
+            Ok(()) => state.last_success_at = Some(now),
+            Err(err) => *state.failures.entry(err.kind()).or_default() += 1,
+        }
+    }
+
+    /// 健康检查返回的json
+    fn health(&self) -> serde_json::Value {
+        let state = self.state.lock().unwrap().clone();
+        serde_json::json!({
+            "status": "ok",
+            "provider": self.provider,
+            "started_at": state.started_at,
+            "last_update_at": state.last_update_at,
+            "last_success_at": state.last_success_at,
+            "records": state.records,
+        })
+    }
+
+    /// prometheus文本格式
+    fn render(&self) -> String {
+        let state = self.state.lock().unwrap().clone();
+        let provider = escape_label(self.provider);
+        let mut out = String::new();
+        out.push_str("# HELP ddns_update_attempts_total Total number of update attempts.\n");
+        out.push_str("# TYPE ddns_update_attempts_total counter\n");
+        let _ = writeln!(
+            out,
+            "ddns_update_attempts_total{{provider=\"{provider}\"}} {}",
+            state.attempts
+        );
+        out.push_str(
+            "# HELP ddns_update_failures_total Total number of failed updates by reason.\n",
+        );
+        out.push_str("# TYPE ddns_update_failures_total counter\n");
+        for (reason, count) in &state.failures {
+            let _ = writeln!(
+                out,
+                "ddns_update_failures_total{{provider=\"{provider}\",reason=\"{reason}\"}} {count}"
+            );
+        }
+        out.push_str(
+            "# HELP ddns_last_success_timestamp_seconds Unix time of the last successful update.\n",
+        );
+        out.push_str("# TYPE ddns_last_success_timestamp_seconds gauge\n");
+        if let Some(last_success_at) = state.last_success_at {
+            let _ = writeln!(
+                out,
+                "ddns_last_success_timestamp_seconds{{provider=\"{provider}\"}} {last_success_at}"
+            );
+        }
+        out.push_str(
+            "# HELP ddns_published_address_info Address currently published for each record.\n",
+        );
+        out.push_str("# TYPE ddns_published_address_info gauge\n");
+        for (record, address) in &state.records {
+            let _ = writeln!(
+                out,
+                "ddns_published_address_info{{provider=\"{provider}\",record=\"{}\",address=\"{}\"}} 1",
+                escape_label(record),
+                escape_label(address)
+            );
+        }
+        out
+    }
+
+    /// 启动http服务，提供/healthz和/metrics
+    pub async fn serve(self, addr: SocketAddr) {
+        let acceptor = match TcpListener::new(addr).try_bind().await {
+            Ok(acceptor) => acceptor,
+            Err(err) => {
+                error!(%addr, error = %err, "metrics server bind failed");
+                return;
+            }
+        };
+        info!(%addr, "metrics server started");
+        let router = Router::new()
+            .push(Router::with_path("healthz").get(HealthHandler(self.clone())))
+            .push(Router::with_path("metrics").get(MetricsHandler(self)));
+        Server::new(acceptor).serve(router).await;
+    }
+}
+
+struct HealthHandler(Metrics);
+
+#[handler]
+impl HealthHandler {
+    async fn handle(&self, res: &mut Response) {
+        res.render(Json(self.0.health()));
+    }
+}
+
+struct MetricsHandler(Metrics);
+
+#[handler]
+impl MetricsHandler {
+    async fn handle(&self, res: &mut Response) {
+        res.render(Text::Plain(self.0.render()));
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new("dynv6");
+        let records = [
+            ("home.dynv6.net".to_string(), "2001:db8::1".to_string()),
+            ("nas.dynv6.net".to_string(), "2001:db8::10".to_string()),
+            ("tv.dynv6.net".to_string(), String::new()),
+        ];
+        metrics.record_update(&records, Ok(()));
+        metrics.record_update(&records, Err(&DdnsError::NoAddress));
+        metrics.record_update(&records, Err(&DdnsError::Auth("bad token".to_string())));
+
+        let text = metrics.render();
+        assert!(text.contains("ddns_update_attempts_total{provider=\"dynv6\"} 3\n"));
+        assert!(
+            text.contains("ddns_update_failures_total{provider=\"dynv6\",reason=\"auth\"} 1\n")
+        );
+        assert!(
+            text.contains(
+                "ddns_update_failures_total{provider=\"dynv6\",reason=\"no_address\"} 1\n"
+            )
+        );
+        assert!(text.contains(
+            "ddns_published_address_info{provider=\"dynv6\",record=\"home.dynv6.net\",address=\"2001:db8::1\"} 1\n"
+        ));
+
+        assert!(text.contains(
+            "ddns_published_address_info{provider=\"dynv6\",record=\"nas.dynv6.net\",address=\"2001:db8::10\"} 1\n"
+        ));
+        assert!(!text.contains("tv.dynv6.net"));
+
+        let health = metrics.health();
+        assert_eq!(health["records"]["home.dynv6.net"], "2001:db8::1");
+        assert_eq!(health["records"]["nas.dynv6.net"], "2001:db8::10");
+    }
+}
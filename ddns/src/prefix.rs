@@ -0,0 +1,363 @@
+use crate::error::Result;
+use crate::verify::VerifyTarget;
+use crate::{DdnsClient, IpAddressType, RunArgs};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+use tracing::{info, warn};
+
+/// 前缀委派模式参数
+#[derive(clap::Args, Debug, Clone)]
+pub struct PrefixArgs {
+    /// Also publish a LAN host in the detected prefix, as `NAME=INTERFACE_ID`,
+    /// e.g. `nas=::211:32ff:fe12:3456`. NAME is relative to the zone. Repeatable
+    #[arg(long = "host")]
+    pub hosts: Vec<Host>,
+    /// Length of the prefix taken from the detected address. Interface id bits
+    /// inside the prefix but beyond /64 select the subnet, e.g. `nas=::1:0:0:0:10` with `56`
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u8).range(1..=128))]
+    pub prefix_len: u8,
+}
+
+/// 局域网主机：记录名称和固定的接口标识
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub name: String,
+    pub interface_id: Ipv6Addr,
+}
+
+impl FromStr for Host {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, interface_id) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=INTERFACE_ID, got `{s}`"))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("empty host name in `{s}`"));
+        }
+        let interface_id = interface_id
+            .trim()
+            .parse()
+            .map_err(|err| format!("invalid interface id in `{s}`: {err}"))?;
+        Ok(Host {
+            name: name.to_string(),
+            interface_id,
+        })
+    }
+}
+
+/// 前缀长度对应的掩码
+fn mask(prefix_len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)
+}
+
+/// 地址所在的前缀
+pub fn network(address: Ipv6Addr, prefix_len: u8) -> Ipv6Addr {
+    Ipv6Addr::from(u128::from(address) & mask(prefix_len))
+}
+
+/// 用前缀替换接口标识的高位
+pub fn combine(prefix: Ipv6Addr, prefix_len: u8, interface_id: Ipv6Addr) -> Ipv6Addr {
+    let mask = mask(prefix_len);
+    Ipv6Addr::from(u128::from(prefix) & mask | u128::from(interface_id) & !mask)
+}
+
+/// 发布本机地址，同时按前缀发布局域网主机的地址
+pub struct PrefixGroup {
+    base: Box<dyn DdnsClient + Send>,
+    hosts: Vec<(Host, Box<dyn DdnsClient + Send>)>,
+    prefix_len: u8,
+    /// 所有记录都发布成功时的本机地址
+    record_ipv6: String,
+}
+
+impl PrefixGroup {
+    /// 服务商不支持同一zone下的其他记录时返回错误
+    pub fn try_new(base: Box<dyn DdnsClient + Send>) -> anyhow::Result<Self> {
+        let args = base.run_args().prefix.clone();
+        let mut hosts = Vec::with_capacity(args.hosts.len());
+        for host in args.hosts {
+            let client = base.host_client(&host.name).ok_or_else(|| {
+                anyhow!(
+                    "{} does not support publishing --host",
+                    base.provider_name()
+                )
+            })?;
+            hosts.push((host, client));
+        }
+        Ok(Self {
+            base,
+            hosts,
+            prefix_len: args.prefix_len,
+            record_ipv6: String::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl DdnsClient for PrefixGroup {
+    fn provider_name(&self) -> &'static str {
+        self.base.provider_name()
+    }
+
+    fn support_type(&self) -> Vec<IpAddressType> {
+        self.base.support_type()
+    }
+
+    fn run_args(&self) -> &RunArgs {
+        self.base.run_args()
+    }
+
+    fn record_name(&self) -> String {
+        self.base.record_name()
+    }
+
+    fn record_ip(&self) -> &str {
+        &self.record_ipv6
+    }
+
+    /// 本机和每个主机的记录分别上报自己的地址
+    fn records(&self) -> Vec<(String, String)> {
+        let mut records = self.base.records();
+        for (_, client) in &self.hosts {
+            records.extend(client.records());
+        }
+        records
+    }
+
+    fn forget_record(&mut self) {
+        self.record_ipv6.clear();
+        self.base.forget_record();
+        for (_, client) in &mut self.hosts {
+            client.forget_record();
+        }
+    }
+
+    fn verify_target(&self) -> VerifyTarget {
+        self.base.verify_target()
+    }
+
+    fn info_log(&self) {
+        self.base.info_log();
+        for (host, client) in &self.hosts {
+            info!(name = %client.record_name(), interface_id = %host.interface_id, prefix_len = self.prefix_len, "prefix host added");
+        }
+    }
+
+    fn describe_update(&self, ip: Ipv6Addr) -> String {
+        let mut lines = vec![self.base.describe_update(ip)];
+        let prefix = network(ip, self.prefix_len);
+        for (host, client) in &self.hosts {
+            let ip = combine(prefix, self.prefix_len, host.interface_id);
+            lines.push(format!(
+                "{}: {}",
+                client.record_name(),
+                client.describe_update(ip)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// 每条记录单独判断是否需要更新，某条失败不影响其他记录
+    async fn update_ip(&mut self, ip: Ipv6Addr) -> Result<()> {
+        let prefix = network(ip, self.prefix_len);
+        let mut first_err = self.base.update_ip(ip).await.err();
+        for (host, client) in &mut self.hosts {
+            let ip = combine(prefix, self.prefix_len, host.interface_id);
+            if let Err(err) = client.update_ip(ip).await {
+                warn!(name = %client.record_name(), error = %err, "update host failed");
+                first_err.get_or_insert(err);
+            }
+        }
+        if let Some(err) = first_err {
+            return Err(err);
+        }
+        let ip = ip.to_string();
+        if ip != self.record_ipv6 {
+            info!(prefix = %format!("{}/{}", prefix, self.prefix_len), hosts = self.hosts.len(), "prefix published");
+            self.record_ipv6 = ip;
+        }
+        Ok(())
+    }
+
+    /// 逐条校验所有记录
+    async fn verify(&mut self) -> Result<()> {
+        if !self.run_args().verify.enabled() {
+            return Ok(());
+        }
+        let res = async {
+            self.base.verify().await?;
+            for (_, client) in &mut self.hosts {
+                client.verify().await?;
+            }
+            Ok(())
+        }
+        .await;
+        if res.is_err() {
+            self.forget_record();
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DdnsError;
+    use crate::testing::{run_args, Call, MockProvider, ScriptedSource};
+    use std::sync::{Arc, Mutex};
+
+    fn addr(s: &str) -> Ipv6Addr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_combine() {
+        let address = addr("2001:db8:1:2:aaaa::10");
+        assert_eq!(network(address, 64), addr("2001:db8:1:2::"));
+        assert_eq!(network(address, 56), addr("2001:db8:1::"));
+        assert_eq!(network(address, 128), address);
+        let prefix = network(address, 56);
+        assert_eq!(
+            combine(prefix, 56, addr("::211:32ff:fe12:3456")),
+            addr("2001:db8:1:0:211:32ff:fe12:3456")
+        );
+        assert_eq!(
+            combine(prefix, 56, addr("::1:0:0:0:20")),
+            addr("2001:db8:1:1::20")
+        );
+    }
+
+    #[test]
+    fn test_parse_host() {
+        assert_eq!(
+            "nas=::10".parse::<Host>().unwrap(),
+            Host {
+                name: "nas".to_string(),
+                interface_id: addr("::10"),
+            }
+        );
+        assert!("nas".parse::<Host>().is_err());
+        assert!("=::10".parse::<Host>().is_err());
+        assert!("nas=10".parse::<Host>().is_err());
+    }
+
+    fn group(
+        source: ScriptedSource,
+        failures: Vec<DdnsError>,
+    ) -> (PrefixGroup, Arc<Mutex<Vec<Call>>>) {
+        let run = run_args(
+            &[
+                "--host",
+                "nas=::211:32ff:fe12:3456",
+                "--host",
+                "tv=::1:0:0:0:20",
+                "--prefix-len",
+                "56",
+            ],
+            source,
+        );
+        let base = MockProvider::new(run, failures);
+        let calls = base.calls();
+        (PrefixGroup::try_new(Box::new(base)).unwrap(), calls)
+    }
+
+    #[tokio::test]
+    async fn test_prefix_change() {
+        let source = ScriptedSource::new(vec![
+            Ok(vec!["2001:db8:1:2::10/64"]),
+            Ok(vec!["2001:db8:1:2::10/64"]),
+            Ok(vec!["2001:db8:9:2::10/64"]),
+        ]);
+        let (mut group, calls) = group(source, vec![]);
+        for _ in 0..3 {
+            group.run_once().await.unwrap();
+        }
+        assert_eq!(group.record_ip(), "2001:db8:9:2::10");
+
+        let calls = calls.lock().unwrap();
+        let published = calls
+            .iter()
+            .map(|call| format!("{} {}", call.name, call.ip))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            published,
+            [
+                "home.example.com 2001:db8:1:2::10",
+                "nas.example.com 2001:db8:1:0:211:32ff:fe12:3456",
+                "tv.example.com 2001:db8:1:1::20",
+                "home.example.com 2001:db8:9:2::10",
+                "nas.example.com 2001:db8:9:0:211:32ff:fe12:3456",
+                "tv.example.com 2001:db8:9:1::20",
+            ]
+        );
+    }
+
+    fn changes(group: &PrefixGroup, old: &[(String, String)]) -> Vec<String> {
+        group
+            .address_changes(old)
+            .into_iter()
+            .map(|change| format!("{} {} -> {}", change.name, change.old, change.new))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_address_changes() {
+        let source = ScriptedSource::new(vec![
+            Ok(vec!["2001:db8:1:2::10/64"]),
+            Ok(vec!["2001:db8:9:2::10/64"]),
+        ]);
+        let failures = vec![DdnsError::Provider {
+            status: 500,
+            message: "internal error".to_string(),
+        }];
+        let (mut group, _) = group(source, failures);
+        assert_eq!(group.record_name(), "home.example.com");
+
+        // 本机记录失败时只通知已发布的主机记录
+        let old = group.records();
+        group.update().await.unwrap_err();
+        assert_eq!(
+            changes(&group, &old),
+            [
+                "nas.example.com  -> 2001:db8:1:0:211:32ff:fe12:3456",
+                "tv.example.com  -> 2001:db8:1:1::20",
+            ]
+        );
+
+        let old = group.records();
+        group.update().await.unwrap();
+        assert_eq!(
+            changes(&group, &old),
+            [
+                "home.example.com  -> 2001:db8:9:2::10",
+                "nas.example.com 2001:db8:1:0:211:32ff:fe12:3456 -> 2001:db8:9:0:211:32ff:fe12:3456",
+                "tv.example.com 2001:db8:1:1::20 -> 2001:db8:9:1::20",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_failure() {
+        let source = ScriptedSource::fixed(vec!["2001:db8:1:2::10/64"]);
+        let failures = vec![DdnsError::Provider {
+            status: 500,
+            message: "internal error".to_string(),
+        }];
+        let (mut group, calls) = group(source, failures);
+        // 本机记录失败时主机记录仍会发布
+        group.run_once().await.unwrap_err();
+        assert_eq!(group.record_ip(), "");
+        assert_eq!(calls.lock().unwrap().len(), 3);
+        // 重试时只发布失败的记录
+        group.run_once().await.unwrap();
+        assert_eq!(group.record_ip(), "2001:db8:1:2::10");
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 4);
+        assert!(calls[3].ok);
+        assert_eq!(calls[3].name, "home.example.com");
+    }
+}
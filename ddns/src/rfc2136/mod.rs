@@ -0,0 +1,640 @@
+use crate::error::{DdnsError, Result};
+use crate::verify::VerifyTarget;
+use crate::{DdnsClient, IpAddressType, RunArgs};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use base64::prelude::*;
+use clap::Parser;
+use common::time::timestamp_s;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time;
+use tracing::{debug, info};
+
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+const TYPE_TSIG: u16 = 250;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+/// UPDATE操作码
+const OPCODE_UPDATE: u16 = 5;
+const RCODE_REFUSED: u8 = 5;
+const RCODE_NOTAUTH: u8 = 9;
+const TSIG_ALGORITHM: &str = "hmac-sha256.";
+/// TSIG允许的时间误差
+const TSIG_FUDGE: u16 = 300;
+/// 等待服务器响应的超时时间
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Update an AAAA record on a DNS server via RFC 2136 dynamic update (TSIG hmac-sha256)
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// DNS server address, e.g. `192.168.1.1:53`.
+    #[arg(short, long)]
+    server: SocketAddr,
+    /// Zone name, e.g. `example.com`.
+    #[arg(short, long)]
+    zone: String,
+    /// Record name, e.g. `home.example.com`.
+    #[arg(short, long)]
+    name: String,
+    /// Record ttl
+    #[arg(long, default_value_t = 300)]
+    ttl: u32,
+    /// TSIG key name.
+    #[arg(long)]
+    key_name: String,
+    /// TSIG key secret, base64 encoded.
+    #[arg(long)]
+    key_secret: String,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Clone)]
+pub struct Rfc2136 {
+    /// DNS server address
+    server: SocketAddr,
+    /// Zone name
+    zone: String,
+    /// Record name
+    name: String,
+    /// Record ttl
+    ttl: u32,
+    /// TSIG key name
+    key_name: String,
+    /// TSIG key secret, base64 encoded
+    key_secret: String,
+    /// Update interval and address watching
+    run: RunArgs,
+    /// ipv6 update before
+    record_ipv6: String,
+}
+
+impl From<Args> for Rfc2136 {
+    fn from(value: Args) -> Self {
+        Rfc2136 {
+            server: value.server,
+            zone: value.zone,
+            name: value.name,
+            ttl: value.ttl,
+            // 计算签名时key名称使用小写
+            key_name: value.key_name.to_lowercase(),
+            key_secret: value.key_secret,
+            run: value.run,
+            record_ipv6: String::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl DdnsClient for Rfc2136 {
+    fn provider_name(&self) -> &'static str {
+        "rfc2136"
+    }
+
+    fn support_type(&self) -> Vec<IpAddressType> {
+        vec![IpAddressType::Ipv6]
+    }
+
+    fn run_args(&self) -> &RunArgs {
+        &self.run
+    }
+
+    fn record_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn record_ip(&self) -> &str {
+        &self.record_ipv6
+    }
+
+    fn forget_record(&mut self) {
+        self.record_ipv6.clear();
+    }
+
+    fn verify_target(&self) -> VerifyTarget {
+        VerifyTarget {
+            zone: self.zone.clone(),
+            name: self.name.clone(),
+        }
+    }
+
+    fn host_client(&self, host: &str) -> Option<Box<dyn DdnsClient + Send>> {
+        Some(Box::new(Rfc2136 {
+            name: format!("{}.{}", host, self.zone.trim_end_matches('.')),
+            record_ipv6: String::new(),
+            ..self.clone()
+        }))
+    }
+
+    fn info_log(&self) {
+        info!(server = %self.server, zone = %self.zone, name = %self.name, key_name = %self.key_name, interval = self.run.interval, "rfc2136 client started");
+    }
+
+    fn describe_update(&self, ip: Ipv6Addr) -> String {
+        format!(
+            "UPDATE zone {} at {}: delete {} AAAA, add {} {} AAAA {}, signed with key {}",
+            self.zone, self.server, self.name, self.name, self.ttl, ip, self.key_name
+        )
+    }
+
+    async fn update_ip(&mut self, address: Ipv6Addr) -> Result<()> {
+        let ipv6 = address.to_string();
+        let ipv6 = ipv6.as_str();
+        if ipv6 == self.record_ipv6 {
+            debug!(name = %self.name, ipv6, "address unchanged");
+            return Ok(());
+        }
+        let secret = BASE64_STANDARD
+            .decode(self.key_secret.trim())
+            .map_err(|err| DdnsError::Auth(format!("invalid tsig secret: {err}")))?;
+
+        let id = rand::random::<u16>();
+        let mut message = build_update(id, &self.zone, &self.name, self.ttl, address);
+        let request_mac = sign_tsig(&mut message, None, &self.key_name, &secret, timestamp_s());
+
+        let bind_addr = if self.server.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind_addr)
+            .await
+            .map_err(anyhow::Error::from)?;
+        socket
+            .connect(self.server)
+            .await
+            .map_err(anyhow::Error::from)?;
+        socket.send(&message).await.map_err(anyhow::Error::from)?;
+        let mut buf = [0u8; 512];
+        let len = time::timeout(RESPONSE_TIMEOUT, socket.recv(&mut buf))
+            .await
+            .map_err(|_| anyhow!("dns server {} response timeout", self.server))?
+            .map_err(anyhow::Error::from)?;
+        check_response(id, &buf[..len])?;
+        verify_response(
+            &buf[..len],
+            &request_mac,
+            &self.key_name,
+            &secret,
+            timestamp_s(),
+        )?;
+
+        info!(name = %self.name, ipv6, "record updated");
+        self.record_ipv6 = ipv6.to_string();
+        Ok(())
+    }
+}
+
+/// 按DNS报文格式写入域名，不使用压缩
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        if !label.is_empty() {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+}
+
+/// 构造UPDATE报文：删除原有AAAA记录后添加新地址
+fn build_update(id: u16, zone: &str, name: &str, ttl: u32, address: Ipv6Addr) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(128);
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&(OPCODE_UPDATE << 11).to_be_bytes());
+    // ZOCOUNT PRCOUNT UPCOUNT ADCOUNT
+    for count in [1u16, 0, 2, 0] {
+        buf.extend_from_slice(&count.to_be_bytes());
+    }
+    // zone section
+    write_name(&mut buf, zone);
+    buf.extend_from_slice(&TYPE_SOA.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    // 删除该名称下所有AAAA记录
+    write_name(&mut buf, name);
+    buf.extend_from_slice(&TYPE_AAAA.to_be_bytes());
+    buf.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    buf.extend_from_slice(&0u32.to_be_bytes());
+    buf.extend_from_slice(&0u16.to_be_bytes());
+    // 添加新记录
+    write_name(&mut buf, name);
+    buf.extend_from_slice(&TYPE_AAAA.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buf.extend_from_slice(&ttl.to_be_bytes());
+    buf.extend_from_slice(&16u16.to_be_bytes());
+    buf.extend_from_slice(&address.octets());
+    buf
+}
+
+/// 读取域名，支持压缩指针，返回小写的名称和名称之后的位置
+fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // 限制跳转次数，防止指针循环
+    for _ in 0..128 {
+        let len = *message.get(offset)? as usize;
+        if len == 0 {
+            return Some((labels.join(".").to_lowercase(), end.unwrap_or(offset + 1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(offset + 2);
+            offset = (read_u16(message, offset)? & 0x3fff) as usize;
+            continue;
+        }
+        let label = message.get(offset + 1..offset + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + len;
+    }
+    None
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    let bytes = message.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// 报文中的TSIG记录
+struct Tsig {
+    /// TSIG记录在报文中的起始位置
+    start: usize,
+    key_name: String,
+    algorithm: String,
+    time_signed: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    error: u16,
+}
+
+/// 读取报文最后一条附加记录中的TSIG，没有签名时返回None
+fn find_tsig(message: &[u8]) -> Option<Tsig> {
+    let zone_count = read_u16(message, 4)?;
+    let record_count = (6..12)
+        .step_by(2)
+        .map(|offset| read_u16(message, offset).map(usize::from))
+        .sum::<Option<usize>>()?;
+    if read_u16(message, 10)? == 0 {
+        return None;
+    }
+    let mut offset = 12;
+    for _ in 0..zone_count {
+        offset = read_name(message, offset)?.1 + 4;
+    }
+    for _ in 0..record_count - 1 {
+        offset = read_name(message, offset)?.1 + 8;
+        offset += 2 + read_u16(message, offset)? as usize;
+    }
+    let start = offset;
+    let (key_name, offset) = read_name(message, start)?;
+    if read_u16(message, offset)? != TYPE_TSIG {
+        return None;
+    }
+    let (algorithm, offset) = read_name(message, offset + 10)?;
+    let mut time_signed = [0u8; 8];
+    time_signed[2..].copy_from_slice(message.get(offset..offset + 6)?);
+    let fudge = read_u16(message, offset + 6)?;
+    let mac_len = read_u16(message, offset + 8)? as usize;
+    let mac = message.get(offset + 10..offset + 10 + mac_len)?.to_vec();
+    // 跳过original id
+    let error = read_u16(message, offset + 12 + mac_len)?;
+    Some(Tsig {
+        start,
+        key_name,
+        algorithm,
+        time_signed: u64::from_be_bytes(time_signed),
+        fudge,
+        mac,
+        error,
+    })
+}
+
+/// TSIG签名的MAC，RFC 8945 4.3.3，响应的MAC还包含请求的MAC，4.3.1
+fn tsig_mac(
+    request_mac: Option<&[u8]>,
+    message: &[u8],
+    key_name: &str,
+    secret: &[u8],
+    time_signed: u64,
+    fudge: u16,
+) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take key of any size");
+    if let Some(request_mac) = request_mac {
+        mac.update(&(request_mac.len() as u16).to_be_bytes());
+        mac.update(request_mac);
+    }
+    mac.update(message);
+    let mut variables = Vec::with_capacity(64);
+    write_name(&mut variables, key_name);
+    variables.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    variables.extend_from_slice(&0u32.to_be_bytes());
+    write_name(&mut variables, TSIG_ALGORITHM);
+    variables.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+    variables.extend_from_slice(&fudge.to_be_bytes());
+    // error other_len
+    variables.extend_from_slice(&0u16.to_be_bytes());
+    variables.extend_from_slice(&0u16.to_be_bytes());
+    mac.update(&variables);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// 在报文末尾追加TSIG记录，签名响应时需要请求的MAC，返回本次签名的MAC
+fn sign_tsig(
+    message: &mut Vec<u8>,
+    request_mac: Option<&[u8]>,
+    key_name: &str,
+    secret: &[u8],
+    time_signed: u64,
+) -> Vec<u8> {
+    let mac = tsig_mac(
+        request_mac,
+        message,
+        key_name,
+        secret,
+        time_signed,
+        TSIG_FUDGE,
+    );
+    let mut rdata = Vec::with_capacity(64);
+    write_name(&mut rdata, TSIG_ALGORITHM);
+    rdata.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+    rdata.extend_from_slice(&TSIG_FUDGE.to_be_bytes());
+    rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+    rdata.extend_from_slice(&mac);
+    // original id
+    rdata.extend_from_slice(&message[0..2]);
+    // error other_len
+    rdata.extend_from_slice(&0u16.to_be_bytes());
+    rdata.extend_from_slice(&0u16.to_be_bytes());
+
+    write_name(message, key_name);
+    message.extend_from_slice(&TYPE_TSIG.to_be_bytes());
+    message.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    message.extend_from_slice(&0u32.to_be_bytes());
+    message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    message.extend_from_slice(&rdata);
+    let additional = u16::from_be_bytes([message[10], message[11]]) + 1;
+    message[10..12].copy_from_slice(&additional.to_be_bytes());
+    mac
+}
+
+/// 校验服务器响应的TSIG签名，RFC 8945 5.3
+fn verify_response(
+    response: &[u8],
+    request_mac: &[u8],
+    key_name: &str,
+    secret: &[u8],
+    now: u64,
+) -> Result<()> {
+    let tsig = find_tsig(response)
+        .ok_or_else(|| DdnsError::Auth("dns response is not signed".to_string()))?;
+    if tsig.key_name != key_name.trim_end_matches('.')
+        || tsig.algorithm != TSIG_ALGORITHM.trim_end_matches('.')
+    {
+        return Err(DdnsError::Auth(format!(
+            "dns response signed with key {} {}",
+            tsig.key_name, tsig.algorithm
+        )));
+    }
+    if tsig.error != 0 {
+        return Err(DdnsError::Auth(format!(
+            "dns response tsig error {}",
+            tsig.error
+        )));
+    }
+    let mut unsigned = response[..tsig.start].to_vec();
+    let additional = u16::from_be_bytes([unsigned[10], unsigned[11]]) - 1;
+    unsigned[10..12].copy_from_slice(&additional.to_be_bytes());
+    let expected = tsig_mac(
+        Some(request_mac),
+        &unsigned,
+        key_name,
+        secret,
+        tsig.time_signed,
+        tsig.fudge,
+    );
+    if expected != tsig.mac {
+        return Err(DdnsError::Auth(
+            "dns response tsig signature mismatch".to_string(),
+        ));
+    }
+    if now.abs_diff(tsig.time_signed) > u64::from(tsig.fudge) {
+        return Err(DdnsError::Auth(
+            "dns response tsig time out of range".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 检查服务器响应的id和rcode
+fn check_response(id: u16, response: &[u8]) -> Result<()> {
+    if response.len() < 12 {
+        return Err(anyhow!("dns response too short").into());
+    }
+    if u16::from_be_bytes([response[0], response[1]]) != id {
+        return Err(anyhow!("dns response id mismatch").into());
+    }
+    match response[3] & 0x0f {
+        0 => Ok(()),
+        rcode @ (RCODE_REFUSED | RCODE_NOTAUTH) => Err(DdnsError::Auth(format!(
+            "dns update rejected, rcode {rcode}"
+        ))),
+        rcode => Err(DdnsError::Provider {
+            status: rcode as u16,
+            message: format!("dns update failed, rcode {rcode}"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "c2VjcmV0LWtleS1mb3ItdGVzdGluZw==";
+    /// 已知答案：key为`ddns-key`，签名时间1700000000，按RFC 8945 4.3的字段顺序用独立实现计算
+    const TIME_SIGNED: u64 = 1_700_000_000;
+    const REQUEST_MAC: &str = "86ac9e2df81388a165dcc9555e2d417aaaa505844becf8dbf03dbb112889fa04";
+    /// 更新home.example.com为2001:db8::1，id为0x1234
+    const SIGNED_REQUEST: &str = "123428000001000000020001076578616d706c6503636f6d000006000104686f6d65076578616d706c6503636f6d00001c00ff00000000000004686f6d65076578616d706c6503636f6d00001c00010000012c001020010db80000000000000000000000010864646e732d6b65790000fa00ff00000000003d0b686d61632d7368613235360000006553f100012c002086ac9e2df81388a165dcc9555e2d417aaaa505844becf8dbf03dbb112889fa04123400000000";
+    /// 服务器对上面请求的NOERROR响应，带回zone section，MAC包含请求的MAC
+    const SIGNED_RESPONSE: &str = "1234a8000001000000000001076578616d706c6503636f6d00000600010864646e732d6b65790000fa00ff00000000003d0b686d61632d7368613235360000006553f100012c0020d3cd2144a9ae1e186b4ebf350faacade819685a10eef803f73cef4f83a70cb56123400000000";
+
+    fn skip_name(message: &[u8], mut offset: usize) -> usize {
+        while message[offset] != 0 {
+            offset += message[offset] as usize + 1;
+        }
+        offset + 1
+    }
+
+    /// 模拟DNS服务器校验TSIG签名，返回rcode
+    fn verify(message: &[u8], secret: &[u8]) -> u8 {
+        let update_count = u16::from_be_bytes([message[8], message[9]]);
+        // zone section
+        let mut offset = skip_name(message, 12) + 4;
+        for _ in 0..update_count {
+            offset = skip_name(message, offset) + 8;
+            let rdlen = u16::from_be_bytes([message[offset], message[offset + 1]]) as usize;
+            offset += 2 + rdlen;
+        }
+        let tsig_start = offset;
+        let rdata = skip_name(message, tsig_start) + 10;
+        let time_offset = skip_name(message, rdata);
+        let mut time_signed = [0u8; 8];
+        time_signed[2..].copy_from_slice(&message[time_offset..time_offset + 6]);
+        let fudge = u16::from_be_bytes([message[time_offset + 6], message[time_offset + 7]]);
+        let mac_len =
+            u16::from_be_bytes([message[time_offset + 8], message[time_offset + 9]]) as usize;
+        let mac = &message[time_offset + 10..time_offset + 10 + mac_len];
+
+        let mut unsigned = message[..tsig_start].to_vec();
+        let additional = u16::from_be_bytes([unsigned[10], unsigned[11]]) - 1;
+        unsigned[10..12].copy_from_slice(&additional.to_be_bytes());
+        let key_name = "ddns-key";
+        let expected = tsig_mac(
+            None,
+            &unsigned,
+            key_name,
+            secret,
+            u64::from_be_bytes(time_signed),
+            fudge,
+        );
+        if expected == mac { 0 } else { RCODE_NOTAUTH }
+    }
+
+    /// 启动只处理一次请求的DNS服务器
+    async fn mock_server(secret: Vec<u8>) -> (SocketAddr, tokio::task::JoinHandle<Vec<u8>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let request = buf[..len].to_vec();
+            let rcode = verify(&request, &secret);
+            let mut response = request[..12].to_vec();
+            response[2] = 0x80 | (OPCODE_UPDATE << 3) as u8;
+            response[3] = rcode;
+            response[4..12].fill(0);
+            // 校验失败时返回不带签名的错误
+            if rcode == 0 {
+                let request_mac = find_tsig(&request).unwrap().mac;
+                sign_tsig(
+                    &mut response,
+                    Some(&request_mac),
+                    "ddns-key",
+                    &secret,
+                    timestamp_s(),
+                );
+            }
+            socket.send_to(&response, peer).await.unwrap();
+            request
+        });
+        (addr, handle)
+    }
+
+    fn client(server: SocketAddr, secret: &str) -> Rfc2136 {
+        Rfc2136::from(Args::parse_from([
+            "rfc2136",
+            "--server",
+            server.to_string().as_str(),
+            "--zone",
+            "example.com",
+            "--name",
+            "home.example.com",
+            "--key-name",
+            "DDNS-KEY",
+            "--key-secret",
+            secret,
+        ]))
+    }
+
+    #[test]
+    fn test_build_update() {
+        let message = build_update(
+            0x1234,
+            "example.com.",
+            "home.example.com",
+            300,
+            "2001:db8::1".parse().unwrap(),
+        );
+        assert_eq!(&message[0..4], &[0x12, 0x34, 0x28, 0x00]);
+        assert_eq!(&message[4..12], &[0, 1, 0, 0, 0, 2, 0, 0]);
+        assert_eq!(&message[12..25], b"\x07example\x03com\x00");
+        assert_eq!(
+            &message[message.len() - 16..],
+            &"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets()
+        );
+    }
+
+    #[test]
+    fn test_sign_tsig_known_answer() {
+        let secret = BASE64_STANDARD.decode(SECRET).unwrap();
+        let mut message = build_update(
+            0x1234,
+            "example.com",
+            "home.example.com",
+            300,
+            "2001:db8::1".parse().unwrap(),
+        );
+        let mac = sign_tsig(&mut message, None, "ddns-key", &secret, TIME_SIGNED);
+        assert_eq!(hex::encode(&mac), REQUEST_MAC);
+        assert_eq!(hex::encode(&message), SIGNED_REQUEST);
+
+        let tsig = find_tsig(&message).unwrap();
+        assert_eq!(tsig.key_name, "ddns-key");
+        assert_eq!(tsig.time_signed, TIME_SIGNED);
+        assert_eq!(tsig.mac, mac);
+    }
+
+    #[test]
+    fn test_verify_response_known_answer() {
+        let secret = BASE64_STANDARD.decode(SECRET).unwrap();
+        let request_mac = hex::decode(REQUEST_MAC).unwrap();
+        let response = hex::decode(SIGNED_RESPONSE).unwrap();
+        let verify = |response: &[u8], request_mac: &[u8], now: u64| {
+            verify_response(response, request_mac, "ddns-key", &secret, now)
+        };
+        verify(&response, &request_mac, TIME_SIGNED + 100).unwrap();
+
+        let mut tampered = response.clone();
+        tampered[3] = RCODE_REFUSED;
+        assert!(matches!(
+            verify(&tampered, &request_mac, TIME_SIGNED),
+            Err(DdnsError::Auth(_))
+        ));
+        assert!(verify(&response, &[0; 32], TIME_SIGNED).is_err());
+        assert!(verify(&response, &request_mac, TIME_SIGNED + 301).is_err());
+        // 去掉TSIG记录
+        let mut unsigned = response[..29].to_vec();
+        unsigned[11] = 0;
+        assert!(verify(&unsigned, &request_mac, TIME_SIGNED).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_ip() {
+        let secret = BASE64_STANDARD.decode(SECRET).unwrap();
+        let (addr, handle) = mock_server(secret).await;
+        let mut client = client(addr, SECRET);
+        client
+            .update_ip("2001:db8::1".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(client.record_ipv6, "2001:db8::1");
+
+        let request = handle.await.unwrap();
+        // ARCOUNT包含TSIG记录
+        assert_eq!(&request[10..12], &[0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_update_ip_bad_key() {
+        let (addr, _handle) = mock_server(b"another secret".to_vec()).await;
+        let mut client = client(addr, SECRET);
+        let err = client
+            .update_ip("2001:db8::1".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DdnsError::Auth(_)));
+        assert!(client.record_ipv6.is_empty());
+    }
+}
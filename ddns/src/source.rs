@@ -0,0 +1,88 @@
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::sync::Arc;
+
+/// 本机地址来源
+pub trait AddressSource: Send + Sync {
+    /// 获取本机ipv6地址列表，地址可以带`/前缀长度`
+    fn ipv6_list(&self) -> anyhow::Result<Vec<String>>;
+}
+
+/// 通过系统命令获取地址
+pub struct SystemSource;
+
+impl AddressSource for SystemSource {
+    fn ipv6_list(&self) -> anyhow::Result<Vec<String>> {
+        crate::get_ipv6_list()
+    }
+}
+
+/// 可在客户端之间共享的地址来源，默认使用系统地址
+#[derive(Clone)]
+pub struct Source(Arc<dyn AddressSource>);
+
+impl Source {
+    pub fn new(source: impl AddressSource + 'static) -> Self {
+        Self(Arc::new(source))
+    }
+
+    pub fn ipv6_list(&self) -> anyhow::Result<Vec<String>> {
+        self.0.ipv6_list()
+    }
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Self::new(SystemSource)
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Source")
+    }
+}
+
+/// 解析地址，忽略`/前缀长度`
+pub fn parse_address(address: &str) -> Option<Ipv6Addr> {
+    address.trim().split('/').next()?.parse().ok()
+}
+
+/// 是否为可以发布的公网单播地址
+pub fn is_global_unicast(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fe80::/10 链路本地地址
+        || first & 0xffc0 == 0xfe80
+        // fc00::/7 唯一本地地址
+        || first & 0xfe00 == 0xfc00)
+}
+
+/// 从检测到的地址中选出要发布的地址：跳过非公网地址，取第一个
+pub fn select_ip(list: &[String]) -> Option<Ipv6Addr> {
+    list.iter()
+        .filter_map(|address| parse_address(address))
+        .find(is_global_unicast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_ip() {
+        let list = [
+            "fe80::1/64",
+            "fd00::1/64",
+            "::1/128",
+            "not an address",
+            "2001:db8::1/64",
+            "2001:db8::2",
+        ]
+        .map(String::from);
+        assert_eq!(select_ip(&list), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(select_ip(&list[..4]), None);
+    }
+}
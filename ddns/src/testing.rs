@@ -0,0 +1,167 @@
+use crate::error::{DdnsError, Result};
+use crate::source::{AddressSource, Source};
+use crate::verify::VerifyTarget;
+use crate::{DdnsClient, IpAddressType, RunArgs};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use clap::Parser;
+use std::collections::VecDeque;
+use std::net::Ipv6Addr;
+use std::sync::{Arc, Mutex};
+use tokio::time::Instant;
+
+/// 按脚本依次返回地址列表，最后一项会一直重复
+pub struct ScriptedSource {
+    script: Mutex<VecDeque<std::result::Result<Vec<String>, String>>>,
+}
+
+impl ScriptedSource {
+    pub fn new(script: Vec<std::result::Result<Vec<&str>, &str>>) -> Self {
+        let script = script
+            .into_iter()
+            .map(|item| {
+                item.map(|list| list.into_iter().map(String::from).collect())
+                    .map_err(String::from)
+            })
+            .collect();
+        Self {
+            script: Mutex::new(script),
+        }
+    }
+
+    /// 每次都返回同一组地址
+    pub fn fixed(list: Vec<&str>) -> Self {
+        Self::new(vec![Ok(list)])
+    }
+}
+
+impl AddressSource for ScriptedSource {
+    fn ipv6_list(&self) -> anyhow::Result<Vec<String>> {
+        let mut script = self.script.lock().unwrap();
+        let item = if script.len() > 1 {
+            script.pop_front()
+        } else {
+            script.front().cloned()
+        };
+        item.unwrap_or(Ok(Vec::new())).map_err(|err| anyhow!(err))
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// 解析命令行形式的运行参数，并替换地址来源
+pub fn run_args(args: &[&str], source: impl AddressSource + 'static) -> RunArgs {
+    let mut run = Cli::parse_from(std::iter::once("ddns").chain(args.iter().copied())).run;
+    run.source = Source::new(source);
+    run
+}
+
+/// 一次发布请求
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub at: Instant,
+    pub name: String,
+    pub ip: String,
+    pub ok: bool,
+}
+
+/// 不访问网络的服务商，按脚本返回错误并记录每次发布请求
+pub struct MockProvider {
+    name: String,
+    run: RunArgs,
+    record_ipv6: String,
+    /// 依次作为发布请求的结果，为空后请求都成功
+    failures: VecDeque<DdnsError>,
+    calls: Arc<Mutex<Vec<Call>>>,
+}
+
+impl MockProvider {
+    pub fn new(run: RunArgs, failures: Vec<DdnsError>) -> Self {
+        Self {
+            name: "home.example.com".to_string(),
+            run,
+            record_ipv6: String::new(),
+            failures: failures.into(),
+            calls: Arc::default(),
+        }
+    }
+
+    /// 客户端移入后台任务后仍可查看请求记录
+    pub fn calls(&self) -> Arc<Mutex<Vec<Call>>> {
+        self.calls.clone()
+    }
+}
+
+#[async_trait]
+impl DdnsClient for MockProvider {
+    fn provider_name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn support_type(&self) -> Vec<IpAddressType> {
+        vec![IpAddressType::Ipv6]
+    }
+
+    fn run_args(&self) -> &RunArgs {
+        &self.run
+    }
+
+    fn record_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn record_ip(&self) -> &str {
+        &self.record_ipv6
+    }
+
+    fn forget_record(&mut self) {
+        self.record_ipv6.clear();
+    }
+
+    fn verify_target(&self) -> VerifyTarget {
+        VerifyTarget {
+            zone: "example.com".to_string(),
+            name: self.record_name(),
+        }
+    }
+
+    fn host_client(&self, host: &str) -> Option<Box<dyn DdnsClient + Send>> {
+        Some(Box::new(MockProvider {
+            name: format!("{host}.example.com"),
+            run: self.run.clone(),
+            record_ipv6: String::new(),
+            failures: VecDeque::new(),
+            calls: self.calls.clone(),
+        }))
+    }
+
+    fn describe_update(&self, ip: Ipv6Addr) -> String {
+        format!("publish {ip}")
+    }
+
+    async fn update_ip(&mut self, address: Ipv6Addr) -> Result<()> {
+        let ipv6 = address.to_string();
+        let ipv6 = ipv6.as_str();
+        if ipv6 == self.record_ipv6 {
+            return Ok(());
+        }
+        let failure = self.failures.pop_front();
+        self.calls.lock().unwrap().push(Call {
+            at: Instant::now(),
+            name: self.name.clone(),
+            ip: ipv6.to_string(),
+            ok: failure.is_none(),
+        });
+        match failure {
+            Some(err) => Err(err),
+            None => {
+                self.record_ipv6 = ipv6.to_string();
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::error::{DdnsError, Result};
+use anyhow::anyhow;
+use hickory_resolver::config::{
+    NameServerConfig, NameServerConfigGroup, ResolverConfig, ResolverOpts,
+};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::xfer::Protocol;
+use hickory_resolver::TokioResolver;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::{debug, info};
+
+/// 两次查询之间的间隔
+const VERIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 更新后校验记录是否生效的参数
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerifyArgs {
+    /// Verify the record on the zone's authoritative nameservers after each update
+    #[arg(long)]
+    pub verify: bool,
+    /// Verify against this resolver instead of the authoritative nameservers, e.g. `1.1.1.1:53`
+    #[arg(long)]
+    pub verify_resolver: Option<SocketAddr>,
+    /// Seconds to wait for the record to match the published address
+    #[arg(long, default_value_t = 120)]
+    pub verify_timeout: u64,
+}
+
+/// 需要校验的记录
+#[derive(Debug, Clone)]
+pub struct VerifyTarget {
+    /// 记录所在的zone，用于查找权威服务器
+    pub zone: String,
+    /// 记录域名
+    pub name: String,
+}
+
+impl VerifyArgs {
+    pub fn enabled(&self) -> bool {
+        self.verify || self.verify_resolver.is_some()
+    }
+
+    /// 等待记录解析到发布的地址，超时返回NotPropagated
+    pub async fn wait_propagated(&self, target: &VerifyTarget, expected: Ipv6Addr) -> Result<()> {
+        let resolver = self.resolver(target).await?;
+        let deadline = Instant::now() + Duration::from_secs(self.verify_timeout);
+        loop {
+            let found = match resolver.ipv6_lookup(target.name.as_str()).await {
+                Ok(lookup) => lookup.iter().map(|aaaa| aaaa.0).collect::<Vec<Ipv6Addr>>(),
+                Err(err) => {
+                    debug!(name = %target.name, error = %err, "verify lookup failed");
+                    Vec::new()
+                }
+            };
+            if found.contains(&expected) {
+                info!(name = %target.name, %expected, "record propagated");
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(DdnsError::NotPropagated {
+                    name: target.name.clone(),
+                    expected,
+                    found,
+                });
+            }
+            debug!(name = %target.name, %expected, ?found, "record not propagated yet");
+            time::sleep(VERIFY_INTERVAL.min(deadline - now)).await;
+        }
+    }
+
+    /// 指定了resolver时直接使用，否则查找zone的权威服务器
+    async fn resolver(&self, target: &VerifyTarget) -> Result<TokioResolver> {
+        let name_servers = match self.verify_resolver {
+            Some(addr) => {
+                let mut group = NameServerConfigGroup::new();
+                group.push(NameServerConfig::new(addr, Protocol::Udp));
+                group.push(NameServerConfig::new(addr, Protocol::Tcp));
+                group
+            }
+            None => {
+                let ips = authoritative_ips(&target.zone).await?;
+                NameServerConfigGroup::from_ips_clear(&ips, 53, true)
+            }
+        };
+        let mut opts = ResolverOpts::default();
+        // 每次查询都要拿到最新结果
+        opts.cache_size = 0;
+        Ok(TokioResolver::builder_with_config(
+            ResolverConfig::from_parts(None, vec![], name_servers),
+            TokioConnectionProvider::default(),
+        )
+        .with_options(opts)
+        .build())
+    }
+}
+
+/// 通过系统resolver查找zone的权威服务器地址
+async fn authoritative_ips(zone: &str) -> Result<Vec<IpAddr>> {
+    let resolver = TokioResolver::builder_tokio()
+        .map_err(anyhow::Error::from)?
+        .build();
+    let ns = resolver
+        .ns_lookup(zone)
+        .await
+        .map_err(|err| anyhow!("lookup NS of {zone} failed: {err}"))?;
+    let mut ips = Vec::new();
+    for name in ns.iter() {
+        match resolver.lookup_ip(name.0.clone()).await {
+            Ok(lookup) => ips.extend(lookup.iter()),
+            Err(err) => debug!(ns = %name.0, error = %err, "lookup nameserver address failed"),
+        }
+    }
+    if ips.is_empty() {
+        return Err(anyhow!("no authoritative nameserver found for {zone}").into());
+    }
+    Ok(ips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UdpSocket;
+
+    /// 对所有AAAA查询返回固定地址的DNS服务器
+    async fn mock_resolver(address: Ipv6Addr) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (_, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let mut end = 12;
+                while buf[end] != 0 {
+                    end += buf[end] as usize + 1;
+                }
+                // 只保留question
+                let question = &buf[12..end + 5];
+                let mut response = buf[..2].to_vec();
+                response.extend_from_slice(&[0x84, 0x00, 0, 1, 0, 1, 0, 0, 0, 0]);
+                response.extend_from_slice(question);
+                response.extend_from_slice(&[0xc0, 0x0c, 0, 28, 0, 1, 0, 0, 0, 60, 0, 16]);
+                response.extend_from_slice(&address.octets());
+                socket.send_to(&response, peer).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    fn args(resolver: SocketAddr) -> VerifyArgs {
+        VerifyArgs {
+            verify: true,
+            verify_resolver: Some(resolver),
+            verify_timeout: 1,
+        }
+    }
+
+    fn target() -> VerifyTarget {
+        VerifyTarget {
+            zone: "example.com".to_string(),
+            name: "home.example.com".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_propagated() {
+        let resolver = mock_resolver("2001:db8::1".parse().unwrap()).await;
+        args(resolver)
+            .wait_propagated(&target(), "2001:db8::1".parse().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_not_propagated() {
+        let resolver = mock_resolver("2001:db8::1".parse().unwrap()).await;
+        let err = args(resolver)
+            .wait_propagated(&target(), "2001:db8::2".parse().unwrap())
+            .await
+            .unwrap_err();
+        match err {
+            DdnsError::NotPropagated { found, .. } => {
+                assert_eq!(found, vec!["2001:db8::1".parse::<Ipv6Addr>().unwrap()]);
+            }
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+}
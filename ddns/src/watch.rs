@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+/// 地址变更事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressEvent {
+    /// 新增地址
+    Added,
+    /// 删除地址
+    Removed,
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::AddressEvent;
+    use netlink_sys::protocols::NETLINK_ROUTE;
+    use netlink_sys::{AsyncSocket, AsyncSocketExt, SocketAddr, TokioSocket};
+
+    /// rtnetlink多播组: ipv4地址变更
+    const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+    /// rtnetlink多播组: ipv6地址变更
+    const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+    const RTM_NEWADDR: u16 = 20;
+    const RTM_DELADDR: u16 = 21;
+    /// nlmsghdr长度
+    const NLMSG_HDRLEN: usize = 16;
+
+    /// 通过netlink订阅内核地址变更通知
+    pub struct AddressWatcher {
+        socket: TokioSocket,
+    }
+
+    impl AddressWatcher {
+        pub fn try_new() -> anyhow::Result<Self> {
+            let mut socket = TokioSocket::new(NETLINK_ROUTE)?;
+            let addr = SocketAddr::new(0, RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR);
+            socket.socket_mut().bind(&addr)?;
+            Ok(Self { socket })
+        }
+
+        /// 等待下一次地址新增或删除
+        pub async fn next_event(&mut self) -> anyhow::Result<AddressEvent> {
+            loop {
+                let (buf, _) = self.socket.recv_from_full().await?;
+                if let Some(event) = parse_events(&buf).into_iter().next() {
+                    return Ok(event);
+                }
+            }
+        }
+    }
+
+    /// 解析一个netlink数据包中的所有地址事件
+    fn parse_events(buf: &[u8]) -> Vec<AddressEvent> {
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset + NLMSG_HDRLEN <= buf.len() {
+            let len = u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap()) as usize;
+            // 长度不合法或消息不完整时丢弃剩余部分
+            if len < NLMSG_HDRLEN || len > buf.len() - offset {
+                break;
+            }
+            let msg_type = u16::from_ne_bytes(buf[offset + 4..offset + 6].try_into().unwrap());
+            match msg_type {
+                RTM_NEWADDR => events.push(AddressEvent::Added),
+                RTM_DELADDR => events.push(AddressEvent::Removed),
+                _ => {}
+            }
+            // 消息按4字节对齐
+            offset += (len + 3) & !3;
+        }
+        events
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// 构造一个netlink消息，按4字节对齐补齐
+        fn message(msg_type: u16, payload: usize) -> Vec<u8> {
+            let len = NLMSG_HDRLEN + payload;
+            let mut buf = Vec::new();
+            buf.extend_from_slice(&(len as u32).to_ne_bytes());
+            buf.extend_from_slice(&msg_type.to_ne_bytes());
+            buf.resize((len + 3) & !3, 0);
+            buf
+        }
+
+        #[test]
+        fn test_parse_events() {
+            // RTM_NEWLINK不是地址事件
+            let buf = [
+                message(RTM_NEWADDR, 5),
+                message(16, 0),
+                message(RTM_DELADDR, 8),
+                message(RTM_NEWADDR, 2),
+            ]
+            .concat();
+            assert_eq!(
+                parse_events(&buf),
+                [
+                    AddressEvent::Added,
+                    AddressEvent::Removed,
+                    AddressEvent::Added
+                ]
+            );
+            assert!(parse_events(&[]).is_empty());
+        }
+
+        #[test]
+        fn test_parse_events_invalid() {
+            // 最后一个消息被截断
+            let mut buf = message(RTM_NEWADDR, 0);
+            buf.extend_from_slice(&message(RTM_DELADDR, 40)[..24]);
+            assert_eq!(parse_events(&buf), [AddressEvent::Added]);
+            // 不完整的消息头
+            let mut buf = message(RTM_NEWADDR, 0);
+            buf.extend_from_slice(&[0; 8]);
+            assert_eq!(parse_events(&buf), [AddressEvent::Added]);
+            // nlmsg_len小于消息头时停止，不会原地循环
+            let mut buf = message(RTM_NEWADDR, 0);
+            buf[..4].copy_from_slice(&4u32.to_ne_bytes());
+            buf.extend_from_slice(&message(RTM_DELADDR, 0));
+            assert!(parse_events(&buf).is_empty());
+            let mut buf = message(RTM_NEWADDR, 0);
+            buf[..4].copy_from_slice(&0u32.to_ne_bytes());
+            assert!(parse_events(&buf).is_empty());
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::AddressWatcher;
+
+/// 非linux系统不支持地址变更通知
+#[cfg(not(target_os = "linux"))]
+pub struct AddressWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl AddressWatcher {
+    pub fn try_new() -> anyhow::Result<Self> {
+        Err(anyhow::anyhow!(
+            "address watching is only supported on linux"
+        ))
+    }
+
+    pub async fn next_event(&mut self) -> anyhow::Result<AddressEvent> {
+        std::future::pending().await
+    }
+}
+
+/// 地址事件来源
+trait EventSource {
+    async fn next_event(&mut self) -> anyhow::Result<AddressEvent>;
+}
+
+impl EventSource for AddressWatcher {
+    async fn next_event(&mut self) -> anyhow::Result<AddressEvent> {
+        AddressWatcher::next_event(self).await
+    }
+}
+
+impl AddressWatcher {
+    /// 等待地址变更，并在`debounce`时间内没有新事件后返回
+    pub async fn changed(&mut self, debounce: Duration) -> anyhow::Result<()> {
+        debounced(self, debounce).await
+    }
+}
+
+/// 等待第一个事件，之后的事件合并到同一次返回
+async fn debounced(source: &mut impl EventSource, debounce: Duration) -> anyhow::Result<()> {
+    source.next_event().await?;
+    loop {
+        tokio::select! {
+            res = source.next_event() => {
+                res?;
+            }
+            _ = tokio::time::sleep(debounce) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use tokio::time::Instant;
+
+    impl EventSource for mpsc::UnboundedReceiver<AddressEvent> {
+        async fn next_event(&mut self) -> anyhow::Result<AddressEvent> {
+            self.recv()
+                .await
+                .ok_or_else(|| anyhow::anyhow!("watcher closed"))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_debounced() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let keep = tx.clone();
+        let start = Instant::now();
+        tokio::spawn(async move {
+            // 5个间隔10ms的事件，1秒后再来一个
+            for _ in 0..5 {
+                tx.send(AddressEvent::Added).unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            tx.send(AddressEvent::Removed).unwrap();
+        });
+        let debounce = Duration::from_millis(100);
+
+        debounced(&mut rx, debounce).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(140));
+        assert!(rx.is_empty());
+
+        debounced(&mut rx, debounce).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(1150));
+        // 发送端关闭后返回错误
+        drop(keep);
+        assert!(debounced(&mut rx, debounce).await.is_err());
+    }
+}